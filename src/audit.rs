@@ -0,0 +1,163 @@
+//! Append-only audit log of destructive actions.
+//!
+//! Every deletion attempt becomes one JSON object on its own line, with who ran
+//! it, where, and which kept file the removed one duplicated. The file is only
+//! ever appended to, so several runs can share one log.
+
+use crate::report::format_timestamp;
+use serde::Serialize;
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// What happened to a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuditAction {
+    /// The path was removed.
+    Deleted,
+    /// Removing the path failed; it is still there.
+    Failed,
+    /// The path was gone or unreadable by the time it was reached.
+    Skipped,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    /// When the action happened (RFC 3339, UTC).
+    pub timestamp: String,
+    /// User running ddupe, from `USER` (or `USERNAME` on Windows).
+    pub user: Option<String>,
+    /// Host ddupe ran on.
+    pub hostname: String,
+    /// What happened.
+    pub action: AuditAction,
+    /// Whether `path` is a file or a directory.
+    pub kind: AuditKind,
+    /// The path acted on.
+    pub path: PathBuf,
+    /// Bytes the path held, if known.
+    pub size: Option<u64>,
+    /// Content hash (or directory signature) the path was matched on.
+    pub hash: String,
+    /// The path that was kept instead.
+    pub keep: PathBuf,
+    /// Why the action failed, for [`AuditAction::Failed`].
+    pub error: Option<String>,
+}
+
+/// What kind of path an [`AuditEntry`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditKind {
+    /// A single file.
+    File,
+    /// A redundant directory, removed once the compared files in it were
+    /// deleted and it was left empty. Its `size` is the bytes those files
+    /// freed, each of which also has its own [`AuditKind::File`] entry.
+    Directory,
+}
+
+/// An open audit log file.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: File,
+    user: Option<String>,
+    hostname: String,
+}
+
+impl AuditLog {
+    /// Open `path` for appending, creating it and its parent directories if
+    /// needed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            user: env::var("USER").or_else(|_| env::var("USERNAME")).ok(),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+        })
+    }
+
+    /// Build an entry stamped with the current time, user and host.
+    pub fn entry(
+        &self,
+        action: AuditAction,
+        kind: AuditKind,
+        path: &Path,
+        hash: &str,
+        keep: &Path,
+    ) -> AuditEntry {
+        AuditEntry {
+            timestamp: format_timestamp(SystemTime::now()),
+            user: self.user.clone(),
+            hostname: self.hostname.clone(),
+            action,
+            kind,
+            path: path.to_path_buf(),
+            size: None,
+            hash: hash.to_string(),
+            keep: keep.to_path_buf(),
+            error: None,
+        }
+    }
+
+    /// Append `entry` as one line and flush it to the file.
+    pub fn record(&mut self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry).map_err(io::Error::other)?;
+        line.push(b'\n');
+        // One write per entry, so concurrent appenders never interleave lines.
+        self.file.write_all(&line)?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tempfile::TempDir;
+
+    #[test]
+    fn entries_are_appended_as_json_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("logs/audit.jsonl");
+
+        for action in [AuditAction::Deleted, AuditAction::Failed] {
+            let mut log = AuditLog::open(&path).unwrap();
+            let mut entry = log.entry(
+                action,
+                AuditKind::File,
+                Path::new("/data/b.txt"),
+                "abc",
+                Path::new("/data/a.txt"),
+            );
+            entry.size = Some(4);
+            log.record(&entry).unwrap();
+        }
+
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["action"], "DELETED");
+        assert_eq!(lines[1]["action"], "FAILED");
+        assert_eq!(lines[0]["kind"], "file");
+        assert_eq!(lines[0]["keep"], "/data/a.txt");
+        assert_eq!(lines[0]["hash"], "abc");
+        assert_eq!(lines[0]["size"], 4);
+        assert!(lines[0]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert!(lines[0]["hostname"].is_string());
+    }
+}
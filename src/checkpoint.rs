@@ -0,0 +1,163 @@
+//! Scan checkpoints, so an interrupted scan can be resumed.
+//!
+//! A [`Checkpoint`] records every file hashed so far together with the size
+//! and modification time it had at the time. When a scan is resumed, files
+//! whose metadata is unchanged reuse the recorded hash instead of being read
+//! again.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Current on-disk checkpoint format version.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Size and modification time of a file, used to detect changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    /// File size in bytes.
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch, if available.
+    pub mtime_ns: Option<u64>,
+}
+
+impl FileStamp {
+    /// Build a stamp from file metadata.
+    pub fn from_metadata(meta: &fs::Metadata) -> Self {
+        let mtime_ns = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .and_then(|d| u64::try_from(d.as_nanos()).ok());
+        Self {
+            size: meta.len(),
+            mtime_ns,
+        }
+    }
+
+    /// Stat `path` and build its stamp.
+    pub fn of(path: &Path) -> io::Result<Self> {
+        fs::metadata(path).map(|meta| Self::from_metadata(&meta))
+    }
+}
+
+/// A hashed file as recorded in a checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    /// Path of the file as discovered by the walk.
+    pub path: PathBuf,
+    /// Metadata at the time the file was hashed.
+    #[serde(flatten)]
+    pub stamp: FileStamp,
+    /// Hex-encoded SHA-256 digest of the file contents.
+    pub hash: String,
+}
+
+/// Progress of a scan: how many files were discovered and which ones have
+/// been hashed so far.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Format version, see [`CHECKPOINT_VERSION`].
+    pub version: u32,
+    /// Root directories the scan was started with.
+    pub roots: Vec<PathBuf>,
+    /// Number of files the walk discovered.
+    pub files_discovered: usize,
+    /// Files hashed so far.
+    pub files: Vec<FileRecord>,
+}
+
+impl Checkpoint {
+    /// Create an empty checkpoint for the given roots.
+    pub fn new(roots: &[PathBuf]) -> Self {
+        Self {
+            version: CHECKPOINT_VERSION,
+            roots: roots.to_vec(),
+            files_discovered: 0,
+            files: Vec::new(),
+        }
+    }
+
+    /// Load a checkpoint from disk.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let checkpoint: Self = serde_json::from_reader(reader).map_err(io::Error::other)?;
+        if checkpoint.version != CHECKPOINT_VERSION {
+            return Err(io::Error::other(format!(
+                "unsupported checkpoint version {} (expected {})",
+                checkpoint.version, CHECKPOINT_VERSION
+            )));
+        }
+        Ok(checkpoint)
+    }
+
+    /// Write the checkpoint to disk atomically, as `save_json` does.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_json(path, self)
+    }
+
+    /// Index the recorded files by path for quick lookups while scanning.
+    pub fn into_lookup(self) -> HashMap<PathBuf, FileRecord> {
+        self.files
+            .into_iter()
+            .map(|record| (record.path.clone(), record))
+            .collect()
+    }
+}
+
+/// Write `value` as JSON to `path`, creating parent directories.
+///
+/// The file is written next to `path` first and then renamed over it, so an
+/// interruption never leaves a truncated file behind.
+pub(crate) fn save_json(path: &Path, value: &impl Serialize) -> io::Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    serde_json::to_writer(&mut writer, value).map_err(io::Error::other)?;
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn checkpoint_round_trips_through_disk() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, b"abc").unwrap();
+        let checkpoint_path = dir.path().join("state/checkpoint.json");
+
+        let mut checkpoint = Checkpoint::new(&[dir.path().to_path_buf()]);
+        checkpoint.files_discovered = 1;
+        checkpoint.files.push(FileRecord {
+            path: file.clone(),
+            stamp: FileStamp::of(&file).unwrap(),
+            hash: "deadbeef".to_string(),
+        });
+        checkpoint.save(&checkpoint_path).unwrap();
+
+        let loaded = Checkpoint::load(&checkpoint_path).unwrap();
+        assert_eq!(loaded.files_discovered, 1);
+        let lookup = loaded.into_lookup();
+        assert_eq!(lookup[&file].hash, "deadbeef");
+        assert_eq!(lookup[&file].stamp.size, 3);
+    }
+}
@@ -0,0 +1,155 @@
+//! Configuration files and named profiles.
+//!
+//! Defaults for command-line options can be kept in TOML files instead of
+//! long shell aliases. Keys are option names (`dry-run`, `sort`, ...); named
+//! profiles live in `[profile.<name>]` tables:
+//!
+//! ```toml
+//! sort = "waste"
+//! skip-hidden = true
+//!
+//! [profile.photos]
+//! top = 20
+//! stats = true
+//! ```
+//!
+//! Two files are read, if they exist: the user's
+//! `$XDG_CONFIG_HOME/ddupe/config.toml` (see [`user_config_path`]) and then
+//! [`PROJECT_CONFIG`] in the current directory, which overrides it. This
+//! module only loads and merges the files; mapping keys onto options is up to
+//! the front-end.
+
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// File name of the project-local configuration file.
+pub const PROJECT_CONFIG: &str = "ddupe.toml";
+
+/// One parsed configuration file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConfigFile {
+    /// Named profiles, from `[profile.<name>]` tables.
+    #[serde(default)]
+    pub profile: BTreeMap<String, toml::Table>,
+    /// Top-level settings, applied whatever the profile.
+    #[serde(flatten)]
+    pub defaults: toml::Table,
+}
+
+impl ConfigFile {
+    /// Parse a configuration file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Every configuration file that was found, lowest precedence first.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    files: Vec<(PathBuf, ConfigFile)>,
+}
+
+impl Config {
+    /// Load the files at `paths` that exist, lowest precedence first.
+    pub fn load(paths: &[PathBuf]) -> io::Result<Self> {
+        let mut files = Vec::new();
+        for path in paths {
+            match ConfigFile::load(path) {
+                Ok(file) => files.push((path.clone(), file)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {e}", path.display()))),
+            }
+        }
+        Ok(Self { files })
+    }
+
+    /// Load the user configuration and then `ddupe.toml` in the current
+    /// directory.
+    pub fn discover() -> io::Result<Self> {
+        let paths: Vec<PathBuf> = user_config_path()
+            .into_iter()
+            .chain(std::iter::once(PathBuf::from(PROJECT_CONFIG)))
+            .collect();
+        Self::load(&paths)
+    }
+
+    /// Paths of the files that were loaded, lowest precedence first.
+    pub fn sources(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+
+    /// Merge every file's settings, then overlay the named `profile`.
+    ///
+    /// Later files override earlier ones key by key, and a profile's keys
+    /// override every top-level setting. Asking for a profile that no file
+    /// defines is an error.
+    pub fn settings(&self, profile: Option<&str>) -> io::Result<toml::Table> {
+        let mut settings = toml::Table::new();
+        for (_, file) in &self.files {
+            settings.extend(file.defaults.clone());
+        }
+        if let Some(name) = profile {
+            let mut found = false;
+            for (_, file) in &self.files {
+                if let Some(table) = file.profile.get(name) {
+                    settings.extend(table.clone());
+                    found = true;
+                }
+            }
+            if !found {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("profile '{name}' is not defined in any configuration file"),
+                ));
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// Where the user's configuration file lives: `$XDG_CONFIG_HOME/ddupe/config.toml`,
+/// falling back to `~/.config/ddupe/config.toml`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("ddupe").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn later_files_and_profiles_override_earlier_settings() {
+        let dir = TempDir::new().unwrap();
+        let user = dir.path().join("user.toml");
+        let project = dir.path().join("ddupe.toml");
+        fs::write(
+            &user,
+            "sort = \"size\"\nstats = true\n[profile.photos]\ntop = 5\n",
+        )
+        .unwrap();
+        fs::write(&project, "sort = \"waste\"\n[profile.photos]\ntop = 20\n").unwrap();
+
+        let config = Config::load(&[user, dir.path().join("missing.toml"), project]).unwrap();
+        assert_eq!(config.sources().count(), 2);
+
+        let defaults = config.settings(None).unwrap();
+        assert_eq!(defaults["sort"].as_str(), Some("waste"));
+        assert_eq!(defaults["stats"].as_bool(), Some(true));
+        assert!(!defaults.contains_key("top"));
+
+        let photos = config.settings(Some("photos")).unwrap();
+        assert_eq!(photos["top"].as_integer(), Some(20));
+
+        assert!(config.settings(Some("music")).is_err());
+    }
+}
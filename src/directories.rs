@@ -0,0 +1,378 @@
+//! Duplicate directory detection.
+//!
+//! Two directories are duplicates when their recursive contents match: the
+//! same relative file names with the same file hashes. [`DirectoryIndex`] is a
+//! [`ScanObserver`] that records every hash during a scan; afterwards
+//! [`DirectoryIndex::duplicate_directories`] reports whole redundant subtrees
+//! instead of one file-level group per file inside them.
+//!
+//! Only the files the scan hashed are compared, so a redundant directory may
+//! still hold things the walk never looked at (hidden files, anything below
+//! the depth limit, files written since). Each group therefore lists the
+//! compared files in [`DirectoryGroup::contents`], and removing a copy means
+//! removing those files, not the whole subtree.
+
+use crate::{DuplicateAnalysis, DuplicateGroup, ScanObserver};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A set of directories with identical recursive contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryGroup {
+    /// Signature of the directory contents (hex-encoded SHA-256 over the
+    /// relative names and hashes of every file inside).
+    pub signature: String,
+    /// Number of files in each copy.
+    pub files: usize,
+    /// Total size in bytes of each copy.
+    pub size: u64,
+    /// The compared files of each copy, as paths relative to the directory
+    /// with their hashes, sorted by path.
+    pub contents: Vec<(PathBuf, String)>,
+    /// The directory we keep.
+    pub keep: PathBuf,
+    /// Redundant copies of `keep`.
+    pub dupes: Vec<PathBuf>,
+}
+
+impl DirectoryGroup {
+    /// Bytes that would be freed by removing every redundant copy.
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.size * self.dupes.len() as u64
+    }
+
+    /// The files to remove for the redundant copy `dupe`: each compared file
+    /// under it, with its hash and the matching file under `keep`.
+    pub fn planned_files<'a>(
+        &'a self,
+        dupe: &'a Path,
+    ) -> impl Iterator<Item = (PathBuf, &'a str, PathBuf)> + 'a {
+        self.contents
+            .iter()
+            .map(move |(rel, hash)| (dupe.join(rel), hash.as_str(), self.keep.join(rel)))
+    }
+}
+
+/// Records file hashes during a scan so duplicate directories can be found.
+#[derive(Debug, Default)]
+pub struct DirectoryIndex {
+    roots: Vec<PathBuf>,
+    discovered: HashSet<PathBuf>,
+    hashes: HashMap<PathBuf, String>,
+    failed: Vec<PathBuf>,
+}
+
+/// Per-directory state while computing signatures.
+#[derive(Default)]
+struct DirNode {
+    files: Vec<(OsString, String)>,
+    subdirs: Vec<PathBuf>,
+    complete: bool,
+}
+
+impl ScanObserver for DirectoryIndex {
+    fn walk_started(&mut self, root: &Path) {
+        self.roots.push(root.to_path_buf());
+    }
+
+    fn file_discovered(&mut self, path: &Path) {
+        self.discovered.insert(path.to_path_buf());
+    }
+
+    fn hashing_finished(&mut self, path: &Path, hash: &str) {
+        self.hashes.insert(path.to_path_buf(), hash.to_string());
+    }
+
+    fn error(&mut self, path: &Path, _error: &io::Error) {
+        self.failed.push(path.to_path_buf());
+    }
+}
+
+impl DirectoryIndex {
+    /// Create an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Find groups of directories with identical recursive contents.
+    ///
+    /// Directories containing any file that could not be hashed (including
+    /// files skipped because the scan was cancelled) are never reported. Only
+    /// the top-most duplicates are returned: a directory whose parent is
+    /// itself a duplicate is covered by its parent's group.
+    pub fn duplicate_directories(&self) -> Vec<DirectoryGroup> {
+        let mut nodes: BTreeMap<PathBuf, DirNode> = BTreeMap::new();
+
+        for (path, hash) in &self.hashes {
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+            nodes
+                .entry(parent.to_path_buf())
+                .or_default()
+                .files
+                .push((name.to_os_string(), hash.clone()));
+            self.link_ancestors(parent, &mut nodes);
+        }
+
+        // Anything that failed (or was never hashed) poisons its ancestors.
+        let incomplete: Vec<&Path> = self
+            .failed
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(
+                self.discovered
+                    .iter()
+                    .filter(|p| !self.hashes.contains_key(*p))
+                    .map(PathBuf::as_path),
+            )
+            .collect();
+        for node in nodes.values_mut() {
+            node.complete = true;
+        }
+        for path in incomplete {
+            for ancestor in path.ancestors() {
+                if let Some(node) = nodes.get_mut(ancestor) {
+                    node.complete = false;
+                }
+            }
+        }
+
+        // Children before parents: deeper paths have more components.
+        let mut order: Vec<PathBuf> = nodes.keys().cloned().collect();
+        order.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+
+        let mut signatures: HashMap<PathBuf, (String, usize)> = HashMap::new();
+        for dir in &order {
+            let node = &nodes[dir];
+            let mut entries: Vec<(OsString, char, String)> = node
+                .files
+                .iter()
+                .map(|(name, hash)| (name.clone(), 'f', hash.clone()))
+                .collect();
+            let mut file_count = node.files.len();
+            for sub in &node.subdirs {
+                let (sig, count) = &signatures[sub];
+                let name = sub.file_name().unwrap_or_default().to_os_string();
+                entries.push((name, 'd', sig.clone()));
+                file_count += count;
+            }
+            entries.sort();
+
+            let mut hasher = Sha256::new();
+            for (name, kind, hash) in &entries {
+                hasher.update([*kind as u8, 0]);
+                hasher.update(name.as_encoded_bytes());
+                hasher.update([0]);
+                hasher.update(hash.as_bytes());
+                hasher.update([b'\n']);
+            }
+            signatures.insert(
+                dir.clone(),
+                (format!("{:x}", hasher.finalize()), file_count),
+            );
+        }
+
+        let mut by_signature: HashMap<&str, Vec<&PathBuf>> = HashMap::new();
+        for (dir, (sig, count)) in &signatures {
+            if *count > 0 && nodes[dir].complete {
+                by_signature.entry(sig.as_str()).or_default().push(dir);
+            }
+        }
+        let duplicated: HashSet<&PathBuf> = by_signature
+            .values()
+            .filter(|dirs| dirs.len() > 1)
+            .flatten()
+            .copied()
+            .collect();
+
+        let mut groups = Vec::new();
+        for (sig, dirs) in by_signature {
+            let mut dirs: Vec<PathBuf> = dirs
+                .into_iter()
+                .filter(|d| {
+                    !d.parent()
+                        .is_some_and(|p| duplicated.contains(&p.to_path_buf()))
+                })
+                .cloned()
+                .collect();
+            if dirs.len() < 2 {
+                continue;
+            }
+            dirs.sort();
+            let keep = dirs.remove(0);
+            let contents = self.contents(&keep);
+            groups.push(DirectoryGroup {
+                signature: sig.to_string(),
+                files: contents.len(),
+                size: self.tree_size(&keep),
+                contents,
+                keep,
+                dupes: dirs,
+            });
+        }
+        groups.sort_by(|a, b| a.keep.cmp(&b.keep));
+        groups
+    }
+
+    /// Register `dir` with its parent, and so on up to the scan root.
+    fn link_ancestors(&self, dir: &Path, nodes: &mut BTreeMap<PathBuf, DirNode>) {
+        let mut child = dir;
+        while !self.roots.iter().any(|r| r == child) {
+            let Some(parent) = child.parent() else {
+                break;
+            };
+            let parent_node = nodes.entry(parent.to_path_buf()).or_default();
+            if parent_node.subdirs.iter().any(|d| d == child) {
+                break;
+            }
+            parent_node.subdirs.push(child.to_path_buf());
+            child = parent;
+        }
+    }
+
+    /// The hashed files under `dir`, relative to it, sorted by path.
+    fn contents(&self, dir: &Path) -> Vec<(PathBuf, String)> {
+        let mut contents: Vec<(PathBuf, String)> = self
+            .hashes
+            .iter()
+            .filter_map(|(path, hash)| {
+                let rel = path.strip_prefix(dir).ok()?;
+                Some((rel.to_path_buf(), hash.clone()))
+            })
+            .collect();
+        contents.sort();
+        contents
+    }
+
+    /// Total size of the hashed files under `dir`.
+    fn tree_size(&self, dir: &Path) -> u64 {
+        self.hashes
+            .keys()
+            .filter(|p| p.starts_with(dir))
+            .filter_map(|p| fs::metadata(p).ok())
+            .map(|meta| meta.len())
+            .sum()
+    }
+}
+
+/// Drop file-level dupes that live inside a redundant directory, since removing
+/// the directory removes them too. Groups left without dupes are dropped and
+/// the totals are recomputed.
+pub fn without_covered_files(
+    analysis: &DuplicateAnalysis,
+    directories: &[DirectoryGroup],
+) -> DuplicateAnalysis {
+    let redundant: Vec<&PathBuf> = directories.iter().flat_map(|g| &g.dupes).collect();
+    let covered = |path: &PathBuf| redundant.iter().any(|dir| path.starts_with(dir));
+
+    let groups: Vec<DuplicateGroup> = analysis
+        .groups
+        .iter()
+        .filter_map(|group| {
+            let dupes: Vec<PathBuf> = group
+                .dupes
+                .iter()
+                .filter(|p| !covered(p))
+                .cloned()
+                .collect();
+            (!dupes.is_empty()).then(|| DuplicateGroup {
+                dupes,
+                ..group.clone()
+            })
+        })
+        .collect();
+
+    DuplicateAnalysis {
+        removable_files: groups
+            .iter()
+            .flat_map(|g| g.dupes.iter().cloned())
+            .collect(),
+        total_saving_bytes: groups.iter().map(|g| g.reclaimable_bytes()).sum(),
+        groups,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scanner;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, rel: &str, contents: &[u8]) {
+        let path = root.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn finds_top_most_duplicate_directories() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        for copy in ["project", "project-copy"] {
+            write(root, &format!("{copy}/README"), b"readme");
+            write(root, &format!("{copy}/src/main.rs"), b"fn main() {}");
+        }
+        // Same files, different names: not a duplicate directory.
+        write(root, "renamed/README.md", b"readme");
+        write(root, "renamed/src/main.rs", b"fn main() {}");
+
+        let mut index = DirectoryIndex::new();
+        let outcome = Scanner::new(root).scan(&mut index);
+        let groups = index.duplicate_directories();
+
+        assert_eq!(groups.len(), 1, "{groups:?}");
+        let group = &groups[0];
+        assert_eq!(group.keep, root.join("project"));
+        assert_eq!(group.dupes, vec![root.join("project-copy")]);
+        assert_eq!(group.files, 2);
+        assert_eq!(group.size, 18);
+        let planned: Vec<PathBuf> = group
+            .planned_files(&group.dupes[0])
+            .map(|(path, _, keep)| {
+                assert_eq!(
+                    keep.strip_prefix(&group.keep),
+                    path.strip_prefix(&group.dupes[0])
+                );
+                path
+            })
+            .collect();
+        assert_eq!(
+            planned,
+            [
+                root.join("project-copy/README"),
+                root.join("project-copy/src/main.rs")
+            ]
+        );
+
+        // The `src` directories are covered by their parents' group, so only
+        // the copies under `renamed` remain as file-level dupes.
+        let remaining = without_covered_files(&outcome.analysis, &groups);
+        assert!(
+            remaining
+                .removable_files
+                .iter()
+                .all(|p| !p.starts_with(root.join("project-copy")))
+        );
+        assert_eq!(remaining.groups.len(), 2);
+    }
+
+    #[test]
+    fn directories_with_unhashed_files_are_not_reported() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        write(root, "a/file", b"x");
+        write(root, "b/file", b"x");
+
+        let mut index = DirectoryIndex::new();
+        Scanner::new(root).scan(&mut index);
+        index.failed.push(root.join("b/unreadable"));
+
+        assert!(index.duplicate_directories().is_empty());
+    }
+}
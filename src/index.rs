@@ -0,0 +1,348 @@
+//! Persistent content index: which paths held which content at the last scan.
+//!
+//! A [`ContentIndex`] maps each SHA-256 hash to the files that had it, with
+//! the size and modification time they had when hashed. Scans record into it
+//! through an [`IndexRecorder`]; [`ContentIndex::query`] then answers "does
+//! this content already exist, and where?" by hashing only the file asked
+//! about, and not even that when no indexed file has the same size.
+
+use crate::{
+    ScanObserver,
+    checkpoint::{FileStamp, save_json},
+    hash_file,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// Current on-disk index format version.
+pub const INDEX_VERSION: u32 = 1;
+
+/// A file as recorded in the index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedFile {
+    /// Absolute path of the file.
+    pub path: PathBuf,
+    /// Metadata at the time the file was hashed.
+    #[serde(flatten)]
+    pub stamp: FileStamp,
+}
+
+/// Hash → files map built up from one or more scans.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentIndex {
+    /// Format version, see [`INDEX_VERSION`].
+    pub version: u32,
+    /// Absolute roots that have been scanned into the index.
+    pub roots: Vec<PathBuf>,
+    /// Files by content hash.
+    pub hashes: BTreeMap<String, Vec<IndexedFile>>,
+    /// Every indexed file size, to rule out most queries without hashing.
+    #[serde(skip)]
+    sizes: HashSet<u64>,
+}
+
+impl Default for ContentIndex {
+    fn default() -> Self {
+        Self {
+            version: INDEX_VERSION,
+            roots: Vec::new(),
+            hashes: BTreeMap::new(),
+            sizes: HashSet::new(),
+        }
+    }
+}
+
+/// Whether an indexed copy still looks like it did when it was hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyStatus {
+    /// Same size and modification time as when indexed.
+    Current,
+    /// Still there, but modified since it was indexed.
+    Changed,
+    /// No longer there.
+    Missing,
+}
+
+/// An indexed file with the queried content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IndexedCopy {
+    /// Path of the indexed file.
+    pub path: PathBuf,
+    /// Whether it is still as indexed.
+    pub status: CopyStatus,
+}
+
+/// The answer to [`ContentIndex::query`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryResult {
+    /// The file asked about.
+    pub path: PathBuf,
+    /// Its size in bytes.
+    pub size: u64,
+    /// Its SHA-256, or `None` if no indexed file has the same size and it was
+    /// not worth hashing.
+    pub hash: Option<String>,
+    /// Indexed files with the same content, other than the queried file.
+    pub copies: Vec<IndexedCopy>,
+}
+
+impl QueryResult {
+    /// Whether at least one indexed copy is still there, unchanged.
+    pub fn found(&self) -> bool {
+        self.copies.iter().any(|c| c.status == CopyStatus::Current)
+    }
+}
+
+impl ContentIndex {
+    /// Create an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load an index from disk.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut index: Self = serde_json::from_reader(reader).map_err(io::Error::other)?;
+        if index.version != INDEX_VERSION {
+            return Err(io::Error::other(format!(
+                "unsupported index version {} (expected {})",
+                index.version, INDEX_VERSION
+            )));
+        }
+        index.sizes = index.files().map(|f| f.stamp.size).collect();
+        Ok(index)
+    }
+
+    /// Load the index at `path`, or start an empty one if there is none yet.
+    pub fn load_or_new(path: &Path) -> io::Result<Self> {
+        match Self::load(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            other => other,
+        }
+    }
+
+    /// Write the index to disk, replacing the file atomically.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save_json(path, self)
+    }
+
+    /// Every indexed file.
+    pub fn files(&self) -> impl Iterator<Item = &IndexedFile> {
+        self.hashes.values().flatten()
+    }
+
+    /// Number of indexed files.
+    pub fn len(&self) -> usize {
+        self.files().count()
+    }
+
+    /// Whether no files are indexed.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Replace what was indexed under `roots` with the files a fresh scan of
+    /// them recorded.
+    ///
+    /// An entry under `roots` is dropped when the scan walked past it or it no
+    /// longer exists. Files the walk skipped (below `--max-depth`, hidden,
+    /// on another filesystem, ...) keep their entries, as do files under
+    /// other roots.
+    pub fn update(&mut self, roots: &[PathBuf], recorder: IndexRecorder) {
+        let roots: Vec<PathBuf> = roots.iter().map(|r| absolute(r)).collect();
+        for files in self.hashes.values_mut() {
+            files.retain(|f| {
+                !roots.iter().any(|root| f.path.starts_with(root))
+                    || (!recorder.walked.contains(&f.path) && f.path.exists())
+            });
+        }
+        self.hashes.retain(|_, files| !files.is_empty());
+        for (hash, file) in recorder.files {
+            self.hashes.entry(hash).or_default().push(file);
+        }
+        for files in self.hashes.values_mut() {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        for root in roots {
+            if !self.roots.contains(&root) {
+                self.roots.push(root);
+            }
+        }
+        self.roots.sort();
+        self.sizes = self.files().map(|f| f.stamp.size).collect();
+    }
+
+    /// Indexed files with content hash `hash`.
+    pub fn lookup(&self, hash: &str) -> &[IndexedFile] {
+        self.hashes.get(hash).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Find indexed copies of the content of `path`.
+    ///
+    /// Only `path` itself is read, and only if some indexed file has its
+    /// size. Each copy found is checked against its recorded size and
+    /// modification time, but not re-read.
+    pub fn query(&self, path: &Path) -> io::Result<QueryResult> {
+        let size = std::fs::metadata(path)?.len();
+        let mut result = QueryResult {
+            path: path.to_path_buf(),
+            size,
+            hash: None,
+            copies: Vec::new(),
+        };
+        if size == 0 || !self.sizes.contains(&size) {
+            return Ok(result);
+        }
+
+        let hash = hash_file(path)?;
+        let queried = absolute(path);
+        result.copies = self
+            .lookup(&hash)
+            .iter()
+            .filter(|f| f.path != queried)
+            .map(|f| IndexedCopy {
+                path: f.path.clone(),
+                status: match FileStamp::of(&f.path) {
+                    Ok(stamp) if stamp == f.stamp => CopyStatus::Current,
+                    Ok(_) => CopyStatus::Changed,
+                    Err(_) => CopyStatus::Missing,
+                },
+            })
+            .collect();
+        result.hash = Some(hash);
+        Ok(result)
+    }
+}
+
+/// Collects every file a scan hashes, for [`ContentIndex::update`].
+///
+/// Empty files are left out: every empty file has the same content.
+#[derive(Debug, Default)]
+pub struct IndexRecorder {
+    files: Vec<(String, IndexedFile)>,
+    /// Every file the walk found, hashed or not.
+    walked: HashSet<PathBuf>,
+}
+
+impl IndexRecorder {
+    /// Create an empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ScanObserver for IndexRecorder {
+    fn file_discovered(&mut self, path: &Path) {
+        self.walked.insert(absolute(path));
+    }
+
+    fn hashing_finished(&mut self, path: &Path, hash: &str) {
+        if let Ok(stamp) = FileStamp::of(path)
+            && stamp.size > 0
+        {
+            self.files.push((
+                hash.to_string(),
+                IndexedFile {
+                    path: absolute(path),
+                    stamp,
+                },
+            ));
+        }
+    }
+}
+
+/// `path` made absolute against the current directory, without resolving
+/// symlinks.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scanner;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn scans_are_indexed_and_queried_without_rescanning() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("archive");
+        fs::create_dir(&archive).unwrap();
+        fs::write(archive.join("a.jpg"), b"photo").unwrap();
+        fs::write(archive.join("b.jpg"), b"other").unwrap();
+        fs::write(archive.join("empty"), b"").unwrap();
+
+        let mut recorder = IndexRecorder::new();
+        Scanner::new(&archive).scan(&mut recorder);
+        let mut index = ContentIndex::new();
+        index.update(std::slice::from_ref(&archive), recorder);
+        assert_eq!(index.len(), 2);
+
+        let path = dir.path().join("index.json");
+        index.save(&path).unwrap();
+        let index = ContentIndex::load(&path).unwrap();
+
+        let incoming = dir.path().join("incoming.jpg");
+        fs::write(&incoming, b"photo").unwrap();
+        let result = index.query(&incoming).unwrap();
+        assert!(result.found());
+        assert_eq!(result.copies[0].path, archive.join("a.jpg"));
+
+        // A size no indexed file has is answered without hashing.
+        fs::write(&incoming, b"longer content").unwrap();
+        let result = index.query(&incoming).unwrap();
+        assert_eq!(result.hash, None);
+        assert!(!result.found());
+
+        // Copies that went away are reported as such.
+        fs::remove_file(archive.join("b.jpg")).unwrap();
+        fs::write(&incoming, b"other").unwrap();
+        let result = index.query(&incoming).unwrap();
+        assert!(!result.found());
+        assert_eq!(result.copies[0].status, CopyStatus::Missing);
+    }
+
+    #[test]
+    fn updating_replaces_only_the_rescanned_roots() {
+        let stamp = FileStamp {
+            size: 1,
+            mtime_ns: None,
+        };
+        let record = |hash: &str, path: &str| {
+            (
+                hash.to_string(),
+                IndexedFile {
+                    path: path.into(),
+                    stamp,
+                },
+            )
+        };
+        let mut index = ContentIndex::new();
+        index.update(
+            &[PathBuf::from("/a"), PathBuf::from("/b")],
+            IndexRecorder {
+                files: vec![record("x", "/a/1"), record("x", "/b/1")],
+                ..IndexRecorder::default()
+            },
+        );
+        index.update(
+            &[PathBuf::from("/a")],
+            IndexRecorder {
+                files: vec![record("y", "/a/2")],
+                ..IndexRecorder::default()
+            },
+        );
+
+        assert_eq!(index.lookup("x").len(), 1);
+        assert_eq!(index.lookup("x")[0].path, PathBuf::from("/b/1"));
+        assert_eq!(index.lookup("y").len(), 1);
+        assert_eq!(index.roots, [PathBuf::from("/a"), PathBuf::from("/b")]);
+    }
+}
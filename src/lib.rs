@@ -4,9 +4,39 @@
 //! - hashing files
 //! - collecting files from a directory tree
 //! - analysing duplicates and computing potential space savings
+//! - running a full scan with progress callbacks (see [`Scanner`])
+//! - checkpointing scan progress so it can be resumed (see [`checkpoint`])
+//! - keeping a persistent index of content to query later (see [`index`])
+//! - reading and writing `sha256sum` and hashdeep manifests (see [`manifest`])
+//! - rendering analysis results as reports (see [`report`])
+//! - summarising where duplicated bytes live (see [`stats`])
+//! - loading configuration files and profiles (see [`config`])
+//! - keeping protected paths out of every deletion plan (see [`safety`])
+//! - tidying up directories emptied by deletions
+//! - recording destructive actions in an audit log (see [`audit`])
+//! - finding the mount each file lives on (see [`mounts`])
+//! - spotting new files that duplicate existing content (see [`watch`])
 //!
 //! The CLI, progress bars, colouring and user interaction live in `src/main.rs`.
 
+pub mod audit;
+pub mod checkpoint;
+pub mod config;
+pub mod directories;
+pub mod index;
+pub mod manifest;
+pub mod mounts;
+pub mod report;
+pub mod safety;
+mod scan;
+pub mod stats;
+pub mod watch;
+
+pub use scan::{
+    CancellationToken, DEFAULT_CHECKPOINT_INTERVAL, EmptyFilePolicy, NoopObserver, ScanObserver,
+    ScanOutcome, Scanner,
+};
+
 use serde::Serialize;
 use sha2::{Digest, Sha256};
 use std::{
@@ -21,6 +51,14 @@ use std::{
 /// This reads the file in chunks to avoid loading large files entirely
 /// into memory.
 pub fn hash_file(path: &Path) -> io::Result<String> {
+    hash_file_with_progress(path, |_| {})
+}
+
+/// Hash a single file like [`hash_file`], calling `on_bytes` with the size of
+/// every chunk as it is read.
+///
+/// Useful for byte-level progress reporting on large files.
+pub fn hash_file_with_progress(path: &Path, mut on_bytes: impl FnMut(u64)) -> io::Result<String> {
     let file = File::open(path)?;
     let mut reader = BufReader::new(file);
     let mut hasher = Sha256::new();
@@ -33,23 +71,132 @@ pub fn hash_file(path: &Path) -> io::Result<String> {
             break;
         }
         hasher.update(&buffer[..bytes_read]);
+        on_bytes(bytes_read as u64);
     }
 
     Ok(format!("{:x}", hasher.finalize()))
 }
 
+/// Settings that control how a directory tree is walked.
+///
+/// Shared by [`collect_files_with`] and [`Scanner::with_walk_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalkOptions {
+    /// Follow symlinks to files and directories. Symlinks are skipped otherwise.
+    pub follow_symlinks: bool,
+    /// Do not descend into directories on a different filesystem (mount) than
+    /// the root, like `find -xdev`.
+    pub one_file_system: bool,
+    /// Only descend this many directory levels below the root. Files directly
+    /// inside the root are at depth 1.
+    pub max_depth: Option<usize>,
+    /// Skip files and directories whose name starts with a `.`. The root
+    /// itself is always walked.
+    pub skip_hidden: bool,
+    /// Which kinds of directory entry are considered files.
+    pub file_types: FileTypeFilter,
+}
+
+/// Kinds of directory entry a walk treats as files; see
+/// [`WalkOptions::file_types`].
+///
+/// Device nodes, FIFOs and sockets are never included: reading them can block
+/// forever or never reach end-of-file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileTypeFilter {
+    /// Regular files only.
+    #[default]
+    Regular,
+    /// Regular files plus symlinks that point at a regular file. Symlinks are
+    /// compared by their target's content but are never deleted.
+    Any,
+}
+
+impl WalkOptions {
+    /// A walker for `root` configured with these options.
+    ///
+    /// walkdir reports symlink loops as errors instead of descending into them.
+    fn walker(&self, root: &Path) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> {
+        let mut walker = walkdir::WalkDir::new(root)
+            .follow_links(self.follow_symlinks)
+            .same_file_system(self.one_file_system);
+        if let Some(depth) = self.max_depth {
+            walker = walker.max_depth(depth);
+        }
+        let skip_hidden = self.skip_hidden;
+        walker
+            .into_iter()
+            .filter_entry(move |entry| !(skip_hidden && entry.depth() > 0 && is_hidden(entry)))
+    }
+
+    /// Whether `entry` is a symlink (not being followed) that should be
+    /// compared by the content of the regular file it points at.
+    fn is_linked_file(&self, entry: &walkdir::DirEntry) -> bool {
+        self.file_types == FileTypeFilter::Any
+            && entry.file_type().is_symlink()
+            && fs::metadata(entry.path()).is_ok_and(|meta| meta.is_file())
+    }
+}
+
+fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    entry.file_name().as_encoded_bytes().starts_with(b".")
+}
+
 /// Collect all files under a root directory (recursively).
 ///
-/// Returns a flat list of file paths. Directories are ignored.
+/// Returns a flat list of file paths. Directories and symlinks are ignored.
 pub fn collect_files(root: &Path) -> Vec<PathBuf> {
-    walkdir::WalkDir::new(root)
-        .into_iter()
+    collect_files_with(root, &WalkOptions::default())
+}
+
+/// Collect all files under a root directory, walking it according to `options`.
+pub fn collect_files_with(root: &Path, options: &WalkOptions) -> Vec<PathBuf> {
+    options
+        .walker(root)
         .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.path().is_file())
+        .filter(|entry| entry.file_type().is_file() || options.is_linked_file(entry))
         .map(|entry| entry.path().to_path_buf())
         .collect()
 }
 
+/// Remove directories left empty after `removed` paths were deleted.
+///
+/// Starting from the parent of each removed path, every directory that is now
+/// empty is removed, walking upwards until a non-empty directory or one of
+/// `roots` is reached. Roots themselves are never removed, and directories
+/// that were already empty but never held a removed path are left alone.
+/// Paths outside every root are ignored.
+///
+/// Returns the directories that were removed, deepest first.
+pub fn prune_empty_dirs(removed: &[PathBuf], roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut candidates: Vec<&Path> = removed
+        .iter()
+        .filter(|p| roots.iter().any(|root| p.starts_with(root)))
+        .filter_map(|p| p.parent())
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    // Deepest first, so children are gone before their parents are checked.
+    candidates.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+
+    let mut pruned = Vec::new();
+    for candidate in candidates {
+        let mut dir = candidate;
+        while !roots.iter().any(|root| root == dir) {
+            // `remove_dir` refuses non-empty directories, which ends the climb.
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+            pruned.push(dir.to_path_buf());
+            match dir.parent() {
+                Some(parent) => dir = parent,
+                None => break,
+            }
+        }
+    }
+    pruned
+}
+
 /// Human-readable byte formatting (KB, MB, GB).
 pub fn format_bytes(bytes: u64) -> String {
     const KB: f64 = 1024.0;
@@ -72,14 +219,25 @@ pub fn format_bytes(bytes: u64) -> String {
 /// A single duplicate group: one "keep" file and zero or more "dupe" files.
 #[derive(Debug, Clone, Serialize)]
 pub struct DuplicateGroup {
+    /// Hex-encoded SHA-256 digest shared by every file in the group.
+    pub hash: String,
+    /// Size in bytes of each file in the group.
+    pub size: u64,
     /// The file we keep in this group.
     pub keep: PathBuf,
     /// Files that are considered duplicates of `keep`.
     pub dupes: Vec<PathBuf>,
 }
 
+impl DuplicateGroup {
+    /// Bytes that would be freed by deleting every dupe in this group.
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.size * self.dupes.len() as u64
+    }
+}
+
 /// Full analysis result of a scan.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct DuplicateAnalysis {
     /// All groups that contain at least one duplicate.
     pub groups: Vec<DuplicateGroup>,
@@ -89,22 +247,74 @@ pub struct DuplicateAnalysis {
     pub total_saving_bytes: u64,
 }
 
+/// Orderings for [`DuplicateAnalysis::sort_groups`].
+///
+/// Every ordering falls back to the `keep` path to break ties, so the result
+/// is fully deterministic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GroupOrder {
+    /// Largest file size first.
+    Size,
+    /// Most copies first.
+    Count,
+    /// By `keep` path, ascending.
+    #[default]
+    Path,
+    /// Most reclaimable bytes first.
+    Waste,
+}
+
 impl DuplicateAnalysis {
     /// Total number of duplicate files (i.e. potential deletions).
     pub fn total_dupes(&self) -> usize {
         self.removable_files.len()
     }
+
+    /// Sort groups by `order`. `removable_files` follows the new group order.
+    pub fn sort_groups(&mut self, order: GroupOrder) {
+        self.groups.sort_by(|a, b| {
+            let primary = match order {
+                GroupOrder::Size => b.size.cmp(&a.size),
+                GroupOrder::Count => b.dupes.len().cmp(&a.dupes.len()),
+                GroupOrder::Path => std::cmp::Ordering::Equal,
+                GroupOrder::Waste => b.reclaimable_bytes().cmp(&a.reclaimable_bytes()),
+            };
+            primary.then_with(|| a.keep.cmp(&b.keep))
+        });
+        self.rebuild_removable_files();
+    }
+
+    /// Keep only the first `n` groups, updating `removable_files` and
+    /// `total_saving_bytes` to match. Sort first to keep the biggest offenders.
+    pub fn truncate(&mut self, n: usize) {
+        if self.groups.len() <= n {
+            return;
+        }
+        self.groups.truncate(n);
+        self.total_saving_bytes = self.groups.iter().map(|g| g.reclaimable_bytes()).sum();
+        self.rebuild_removable_files();
+    }
+
+    fn rebuild_removable_files(&mut self) {
+        self.removable_files = self
+            .groups
+            .iter()
+            .flat_map(|g| g.dupes.iter().cloned())
+            .collect();
+    }
 }
 
 /// Given a mapping from content-hash -> list of files, build a `DuplicateAnalysis`.
 ///
-/// Any hash that only has a single file is ignored (not a duplicate).
+/// Any hash that only has a single file is ignored (not a duplicate). Groups
+/// are returned in [`GroupOrder::Path`] order, so the result does not depend on
+/// the iteration order of `hash_map`.
 pub fn analyse_duplicates(hash_map: HashMap<String, Vec<PathBuf>>) -> DuplicateAnalysis {
     let mut groups = Vec::new();
     let mut removable_files = Vec::new();
     let mut total_saving_bytes: u64 = 0;
 
-    for (_hash, mut files) in hash_map {
+    for (hash, mut files) in hash_map {
         if files.len() <= 1 {
             continue;
         }
@@ -114,6 +324,7 @@ pub fn analyse_duplicates(hash_map: HashMap<String, Vec<PathBuf>>) -> DuplicateA
 
         let keep = files[0].clone();
         let dupes = files[1..].to_vec();
+        let size = fs::metadata(&keep).map(|meta| meta.len()).unwrap_or(0);
 
         for dupe in &dupes {
             if let Ok(meta) = fs::metadata(dupe) {
@@ -123,21 +334,27 @@ pub fn analyse_duplicates(hash_map: HashMap<String, Vec<PathBuf>>) -> DuplicateA
 
         removable_files.extend(dupes.clone());
 
-        groups.push(DuplicateGroup { keep, dupes });
+        groups.push(DuplicateGroup {
+            hash,
+            size,
+            keep,
+            dupes,
+        });
     }
 
-    DuplicateAnalysis {
+    let mut analysis = DuplicateAnalysis {
         groups,
         removable_files,
         total_saving_bytes,
-    }
+    };
+    analysis.sort_groups(GroupOrder::Path);
+    analysis
 }
 
 /// Build a hash map: SHA-256 hash -> list of files with that hash.
 ///
 /// This version does **not** handle any UI/progress, so it is easy to test.
-/// The CLI wrapper in `main.rs` can add progress bars while calling `hash_file`
-/// if desired.
+/// Use [`Scanner`] with a [`ScanObserver`] when progress reporting is needed.
 pub fn build_hash_map(files: &[PathBuf]) -> HashMap<String, Vec<PathBuf>> {
     let mut map: HashMap<String, Vec<PathBuf>> = HashMap::new();
 
@@ -195,6 +412,62 @@ mod tests {
         assert_eq!(names, expected);
     }
 
+    #[test]
+    fn collect_files_with_applies_walk_filters() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        let _ = write_file(&dir, "top.txt", b"1");
+        let _ = write_file(&dir, "a/mid.txt", b"2");
+        let _ = write_file(&dir, "a/b/deep.txt", b"3");
+        let _ = write_file(&dir, ".hidden", b"4");
+        let _ = write_file(&dir, ".git/config", b"5");
+        #[cfg(unix)]
+        let _socket = std::os::unix::net::UnixListener::bind(dir.path().join("sock")).unwrap();
+
+        let names = |options: &WalkOptions| {
+            let mut names: Vec<String> = collect_files_with(dir.path(), options)
+                .iter()
+                .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(
+            names(&WalkOptions::default()),
+            [".hidden", "config", "deep.txt", "mid.txt", "top.txt"]
+        );
+        assert_eq!(
+            names(&WalkOptions {
+                max_depth: Some(2),
+                skip_hidden: true,
+                ..WalkOptions::default()
+            }),
+            ["mid.txt", "top.txt"]
+        );
+    }
+
+    #[test]
+    fn prune_empty_dirs_removes_only_directories_emptied_by_deletion() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().to_path_buf();
+        fs::create_dir_all(root.join("old/photos/2019")).unwrap();
+        fs::create_dir_all(root.join("already-empty")).unwrap();
+        let _keep = write_file(&dir, "old/notes.txt", b"keep");
+        let dupe = write_file(&dir, "old/photos/2019/a.jpg", b"dupe");
+        fs::remove_file(&dupe).unwrap();
+
+        let pruned = prune_empty_dirs(&[dupe], std::slice::from_ref(&root));
+
+        assert_eq!(
+            pruned,
+            [root.join("old/photos/2019"), root.join("old/photos")]
+        );
+        assert!(root.join("old/notes.txt").exists());
+        assert!(root.join("already-empty").exists());
+    }
+
     #[test]
     fn format_bytes_handles_common_boundaries() {
         assert_eq!(format_bytes(999), "999 B");
@@ -223,6 +496,8 @@ mod tests {
         assert_eq!(analysis.groups.len(), 1);
         let group = &analysis.groups[0];
         assert_eq!(group.keep, keep);
+        assert_eq!(group.hash, "dup");
+        assert_eq!(group.size, 1);
 
         let mut dupes = group.dupes.clone();
         dupes.sort();
@@ -237,6 +512,59 @@ mod tests {
         );
     }
 
+    fn group(keep: &str, size: u64, dupes: usize) -> DuplicateGroup {
+        DuplicateGroup {
+            hash: keep.to_string(),
+            size,
+            keep: PathBuf::from(keep),
+            dupes: (0..dupes)
+                .map(|i| PathBuf::from(format!("{keep}-{i}")))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn sort_groups_orders_deterministically() {
+        let mut analysis = DuplicateAnalysis {
+            groups: vec![group("/c", 10, 1), group("/a", 1, 5), group("/b", 10, 3)],
+            ..DuplicateAnalysis::default()
+        };
+        let keeps = |a: &DuplicateAnalysis| {
+            a.groups
+                .iter()
+                .map(|g| g.keep.display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        analysis.sort_groups(GroupOrder::Path);
+        assert_eq!(keeps(&analysis), ["/a", "/b", "/c"]);
+        assert_eq!(analysis.removable_files[0], PathBuf::from("/a-0"));
+
+        analysis.sort_groups(GroupOrder::Size);
+        assert_eq!(keeps(&analysis), ["/b", "/c", "/a"]);
+
+        analysis.sort_groups(GroupOrder::Count);
+        assert_eq!(keeps(&analysis), ["/a", "/b", "/c"]);
+
+        analysis.sort_groups(GroupOrder::Waste);
+        assert_eq!(keeps(&analysis), ["/b", "/c", "/a"]);
+    }
+
+    #[test]
+    fn truncate_keeps_top_groups_and_recomputes_totals() {
+        let mut analysis = DuplicateAnalysis {
+            groups: vec![group("/a", 10, 3), group("/b", 1, 1)],
+            removable_files: vec![],
+            total_saving_bytes: 31,
+        };
+
+        analysis.truncate(1);
+
+        assert_eq!(analysis.groups.len(), 1);
+        assert_eq!(analysis.total_dupes(), 3);
+        assert_eq!(analysis.total_saving_bytes, 30);
+    }
+
     #[test]
     fn build_hash_map_groups_identical_content() {
         let dir = TempDir::new().unwrap();
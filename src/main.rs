@@ -5,19 +5,47 @@
 //! - progress bars (indicatif)
 //! - coloured output (colored)
 //! - confirmation prompts and deletion
+//! - the full-screen review (`tui.rs`)
 //!
 //! Core logic for hashing and duplicate analysis lives in `lib.rs`.
 
-use clap::Parser;
+mod tui;
+
+use clap::{
+    Arg, ArgMatches, Command, CommandFactory, Parser, Subcommand, ValueEnum, parser::ValueSource,
+};
 use colored::*;
-use ddupe::{analyse_duplicates, collect_files, format_bytes};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use serde::Serialize;
+use ddupe::{
+    CancellationToken, DuplicateGroup, EmptyFilePolicy, FileTypeFilter, GroupOrder, NoopObserver,
+    ScanObserver, Scanner, WalkOptions,
+    audit::{AuditAction, AuditKind, AuditLog},
+    config::Config,
+    directories::{self, DirectoryGroup, DirectoryIndex},
+    format_bytes,
+    index::{ContentIndex, CopyStatus, IndexRecorder},
+    manifest::{self, HashRecorder, ManifestEntry, ManifestFormat},
+    mounts::MountResolver,
+    report::{NdjsonWriter, ReportContext},
+    safety::{DeletionLimits, FailureBreaker, ProtectedPaths, first_outside_roots},
+    stats::{WasteEntry, WasteStats},
+    watch::WatchIndex,
+};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use notify::{
+    EventKind, RecursiveMode, Watcher,
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+};
 use std::{
+    collections::HashMap,
+    ffi::OsString,
     fs,
-    io::{self, Write},
+    io::{self, IsTerminal, Write},
     path::{Path, PathBuf},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::RecvTimeoutError,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 /// Command-line arguments for the `ddupe` tool.
@@ -30,40 +58,463 @@ use std::{
     long_about = "ddupe recursively scans a directory, hashes file contents using SHA-256,\n\
                   groups files with identical content, and can optionally delete duplicates,\n\
                   keeping one file per group. By default it asks for confirmation before\n\
-                  deleting, and with --dry-run it will never delete anything."
+                  deleting, and with --dry-run it will never delete anything.",
+    override_usage = "ddupe [OPTIONS] <PATH>\n       ddupe [OPTIONS] watch [--delete] <ROOTS>...\n       ddupe [OPTIONS] query [--json] <FILES>...",
+    subcommand_negates_reqs = true
 )]
 struct Args {
     /// Directory to scan recursively for duplicate files
-    path: PathBuf,
+    #[arg(required = true)]
+    path: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
 
     /// Dry run: do not delete files, only show what *would* be removed
     #[arg(long)]
     dry_run: bool,
 
+    /// Turn off a `dry-run = true` set in a configuration file
+    #[arg(long = "no-dry-run", conflicts_with = "dry_run")]
+    no_dry_run: bool,
+
     /// Interactive deletion: review each duplicate one by one
     #[arg(short = 'i', long = "interactive")]
     interactive: bool,
 
+    /// Review groups in a full-screen terminal UI: mark files keep/delete,
+    /// search, undo, then apply everything at once
+    #[arg(
+        long,
+        conflicts_with_all = ["interactive", "dry_run", "dirs", "json_output", "csv_output", "html_output", "format", "porcelain", "null", "output_style", "summarize"]
+    )]
+    tui: bool,
+
     /// Write analysis to a JSON file (implies dry-run; never deletes)
     #[arg(long = "json-output", value_name = "FILE")]
     json_output: Option<PathBuf>,
+
+    /// Write one CSV row per file to a file (implies dry-run; never deletes)
+    #[arg(long = "csv-output", value_name = "FILE")]
+    csv_output: Option<PathBuf>,
+
+    /// Write a self-contained HTML report to a file (implies dry-run; never deletes)
+    #[arg(long = "html-output", value_name = "FILE")]
+    html_output: Option<PathBuf>,
+
+    /// Output format. `ndjson` streams one JSON object per duplicate group as
+    /// soon as it is final, then a summary record (implies dry-run; never deletes)
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        conflicts_with_all = ["json_output", "csv_output", "html_output", "top", "sort", "stats", "dirs", "tui", "interactive", "prune_empty_dirs"]
+    )]
+    format: OutputFormat,
+
+    /// Write `--format ndjson` records to this file instead of stdout
+    #[arg(long, value_name = "FILE", requires = "format")]
+    output: Option<PathBuf>,
+
+    /// Print a stable, tab-separated listing (group id, role, size, path) with
+    /// no banner, colours, progress bars or prompts. Never deletes
+    #[arg(
+        long,
+        conflicts_with_all = ["interactive", "json_output", "csv_output", "html_output", "format"]
+    )]
+    porcelain: bool,
+
+    /// Terminate porcelain records with NUL instead of newline (implies --porcelain)
+    #[arg(
+        short = '0',
+        long = "null",
+        conflicts_with_all = ["interactive", "json_output", "csv_output", "html_output", "format"]
+    )]
+    null: bool,
+
+    /// Listing style. `fdupes` prints groups as blank-line-separated path lists,
+    /// exactly like `fdupes -r`, with no banner or prompts (never deletes)
+    #[arg(
+        long = "output-style",
+        value_enum,
+        default_value_t = OutputStyle::Ddupe,
+        conflicts_with_all = ["interactive", "json_output", "csv_output", "html_output", "format", "porcelain", "null"]
+    )]
+    output_style: OutputStyle,
+
+    /// Print only an `fdupes -m` style summary of duplicate files (never deletes)
+    #[arg(
+        short = 'm',
+        long = "summarize",
+        conflicts_with_all = ["interactive", "json_output", "csv_output", "html_output", "format", "porcelain", "null"]
+    )]
+    summarize: bool,
+
+    /// Accepted for fdupes compatibility; ddupe always recurses
+    #[arg(short = 'r', long = "recurse")]
+    recurse: bool,
+
+    /// Order of duplicate groups in listings and reports
+    #[arg(long, value_enum, default_value_t = SortOrder::Path)]
+    sort: SortOrder,
+
+    /// Only show the first N groups after sorting (e.g. `--sort waste --top 10`)
+    #[arg(long, value_name = "N")]
+    top: Option<usize>,
+
+    /// Print reclaimable space per top-level directory, extension and size band,
+    /// plus the directory pairs sharing the most duplicated content
+    #[arg(long)]
+    stats: bool,
+
+    /// Detect directories whose recursive contents are identical and report
+    /// them as directory-level groups; removing one deletes the files that
+    /// were compared, then the directories left empty
+    #[arg(
+        long,
+        conflicts_with_all = ["json_output", "csv_output", "html_output", "format", "porcelain", "null", "output_style", "summarize"]
+    )]
+    dirs: bool,
+
+    /// Follow symlinks while scanning. Each file is hashed once however many
+    /// paths lead to it, loops are skipped with a warning, and files reached
+    /// only through a symlink are never deleted
+    #[arg(long = "follow-symlinks", conflicts_with = "dirs")]
+    follow_symlinks: bool,
+
+    /// Do not cross into other filesystems (network shares, FUSE mounts, ...)
+    /// while walking
+    #[arg(short = 'x', long = "one-file-system")]
+    one_file_system: bool,
+
+    /// Only descend N directory levels below the scanned directory
+    /// (1 = files directly inside it)
+    #[arg(long = "max-depth", value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Skip hidden files and directories (names starting with `.`)
+    #[arg(long = "skip-hidden", global = true)]
+    skip_hidden: bool,
+
+    /// Which entries to hash. Device nodes, FIFOs and sockets are always skipped
+    #[arg(long = "type", value_enum, default_value_t = FileTypes::Regular)]
+    file_types: FileTypes,
+
+    /// Show which mount each duplicate group lives on. Copies on different
+    /// filesystems cannot be hard-linked to each other
+    #[arg(long = "show-mounts")]
+    show_mounts: bool,
+
+    /// What to do with zero-byte files, which all share the same hash
+    #[arg(long = "empty-files", value_enum, default_value_t = EmptyFiles::Ignore)]
+    empty_files: EmptyFiles,
+
+    /// After deleting, remove directories that the deletions left empty
+    #[arg(long = "prune-empty-dirs")]
+    prune_empty_dirs: bool,
+
+    /// Resume from (and periodically save progress to) a checkpoint file.
+    /// Files whose size and modification time are unchanged are not rehashed.
+    #[arg(long = "resume", value_name = "CHECKPOINT")]
+    resume: Option<PathBuf>,
+
+    /// Also compare against the files listed in a sha256sum or hashdeep
+    /// manifest, such as one written elsewhere for an offline archive
+    /// (repeatable). Listed files are kept and never touched; scanned copies
+    /// of them are reported, but only deleted with --trust-manifest
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["format", "dirs", "follow_symlinks"]
+    )]
+    manifest: Vec<PathBuf>,
+
+    /// Delete scanned files whose only other copy is listed in a --manifest.
+    /// ddupe cannot check that the listed copy still exists
+    #[arg(long = "trust-manifest", requires = "manifest")]
+    trust_manifest: bool,
+
+    /// Write the hash of every scanned file, with its absolute path, to FILE
+    /// as a manifest
+    #[arg(long = "manifest-output", value_name = "FILE")]
+    manifest_output: Option<PathBuf>,
+
+    /// Format for --manifest-output
+    #[arg(long = "manifest-format", value_enum, default_value_t = ManifestFormats::Sha256sum)]
+    manifest_format: ManifestFormats,
+
+    /// Never delete or otherwise touch paths matching GLOB, or anything under
+    /// them (repeatable, e.g. `--protect '/etc' --protect '**/.ssh'`)
+    #[arg(long, value_name = "GLOB", global = true)]
+    protect: Vec<String>,
+
+    /// Refuse to delete anything if the plan would delete more than N files
+    /// (`watch --delete` stops before its N+1th deletion)
+    #[arg(long = "max-delete-count", value_name = "N", global = true)]
+    max_delete_count: Option<u64>,
+
+    /// Refuse to delete anything if the plan would free more than BYTES bytes
+    /// (`watch --delete` stops before going over BYTES in total)
+    #[arg(long = "max-delete-bytes", value_name = "BYTES", global = true)]
+    max_delete_bytes: Option<u64>,
+
+    /// Stop deleting once N deletions have failed
+    #[arg(long = "max-failures", value_name = "N", global = true)]
+    max_failures: Option<u64>,
+
+    /// Append a JSON line for every deletion (and failed or skipped attempt)
+    /// to FILE, with time, user, host, size, hash and the file kept instead
+    #[arg(long = "audit-log", value_name = "FILE", global = true)]
+    audit_log: Option<PathBuf>,
+
+    /// Record every hashed file in this persistent index (updated after each
+    /// complete scan), for `ddupe query`
+    #[arg(long, value_name = "FILE", global = true)]
+    index: Option<PathBuf>,
+
+    /// Apply the named `[profile.NAME]` from the configuration files
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Ignore ddupe.toml and the user configuration file
+    #[arg(long = "no-config", conflicts_with = "profile", global = true)]
+    no_config: bool,
 }
 
-/// Data structure for JSON output.
-#[derive(Serialize)]
-struct JsonGroup {
-    files: Vec<String>,
+#[derive(Subcommand)]
+enum Commands {
+    /// Watch directories and report new files that duplicate existing content
+    /// as soon as they are written (on Linux, when closed; elsewhere, once
+    /// writes have settled for 2 seconds)
+    Watch(WatchArgs),
+    /// Look files up in the --index built by earlier scans, without
+    /// rescanning: does this content already exist, and where?
+    Query(QueryArgs),
 }
 
-#[derive(Serialize)]
-struct JsonReport {
-    roots: Vec<String>,
-    duplicate_groups: Vec<JsonGroup>,
-    removable_count: usize,
-    savings_bytes: u64,
-    dry_run: bool,
-    interactive: bool,
-    mode: &'static str,
+#[derive(clap::Args)]
+struct QueryArgs {
+    /// Files to look up
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Print one JSON object per file instead of text
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct WatchArgs {
+    /// Directories to index and watch recursively
+    #[arg(required = true)]
+    roots: Vec<PathBuf>,
+
+    /// Delete a new file that duplicates existing content, keeping the
+    /// existing copy (honours --protect and --audit-log)
+    #[arg(long)]
+    delete: bool,
+}
+
+/// Output formats selectable with `--format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable, colourised listing (default)
+    Text,
+    /// Newline-delimited JSON, streamed while scanning
+    Ndjson,
+}
+
+/// Listing styles selectable with `--output-style`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputStyle {
+    /// ddupe's own KEEP/DUPE listing (default)
+    Ddupe,
+    /// Blank-line-separated path lists, like `fdupes -r`
+    Fdupes,
+}
+
+/// Group orderings selectable with `--sort`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum SortOrder {
+    /// Largest file size first
+    Size,
+    /// Most copies first
+    Count,
+    /// By path of the kept file (default)
+    Path,
+    /// Most reclaimable space first
+    Waste,
+}
+
+/// Entry kinds selectable with `--type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum FileTypes {
+    /// Regular files only (default)
+    Regular,
+    /// Regular files plus symlinks to regular files (compared, never deleted)
+    Any,
+}
+
+impl From<FileTypes> for FileTypeFilter {
+    fn from(types: FileTypes) -> Self {
+        match types {
+            FileTypes::Regular => FileTypeFilter::Regular,
+            FileTypes::Any => FileTypeFilter::Any,
+        }
+    }
+}
+
+/// Manifest formats selectable with `--manifest-format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ManifestFormats {
+    /// `<hash>  <path>` lines, as read by `sha256sum -c` (default)
+    Sha256sum,
+    /// hashdeep's `size,sha256,filename` lines, with its header
+    Hashdeep,
+}
+
+impl From<ManifestFormats> for ManifestFormat {
+    fn from(format: ManifestFormats) -> Self {
+        match format {
+            ManifestFormats::Sha256sum => ManifestFormat::Sha256sum,
+            ManifestFormats::Hashdeep => ManifestFormat::Hashdeep,
+        }
+    }
+}
+
+/// Empty-file policies selectable with `--empty-files`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum EmptyFiles {
+    /// Leave empty files out entirely (default)
+    Ignore,
+    /// List empty files separately; never treat them as duplicates
+    Report,
+    /// Group empty files as duplicates of each other
+    Include,
+}
+
+impl From<EmptyFiles> for EmptyFilePolicy {
+    fn from(policy: EmptyFiles) -> Self {
+        match policy {
+            EmptyFiles::Ignore => EmptyFilePolicy::Ignore,
+            EmptyFiles::Report => EmptyFilePolicy::Report,
+            EmptyFiles::Include => EmptyFilePolicy::Include,
+        }
+    }
+}
+
+impl From<SortOrder> for GroupOrder {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Size => GroupOrder::Size,
+            SortOrder::Count => GroupOrder::Count,
+            SortOrder::Path => GroupOrder::Path,
+            SortOrder::Waste => GroupOrder::Waste,
+        }
+    }
+}
+
+/// Scan observer that writes each duplicate group as an NDJSON record the
+/// moment the scanner finalises it.
+struct NdjsonObserver {
+    writer: NdjsonWriter<Box<dyn Write>>,
+    /// Protected files are left out of the records, like every other listing.
+    protect: ProtectedPaths,
+    /// First write error, if any. Later groups are not written after a failure.
+    error: Option<io::Error>,
+}
+
+impl ScanObserver for NdjsonObserver {
+    fn group_found(&mut self, group: &DuplicateGroup) {
+        let dupes: Vec<PathBuf> = group
+            .dupes
+            .iter()
+            .filter(|p| !self.protect.is_protected(p))
+            .cloned()
+            .collect();
+        if dupes.is_empty() {
+            return;
+        }
+        let group = DuplicateGroup {
+            dupes,
+            ..group.clone()
+        };
+        if self.error.is_none()
+            && let Err(e) = self.writer.write_group(&group)
+        {
+            self.error = Some(e);
+        }
+    }
+}
+
+/// Scan observer that renders progress with indicatif: an overall bar for the
+/// number of files hashed plus a spinner showing the file currently in flight.
+struct ProgressObserver {
+    bar: ProgressBar,
+    current: ProgressBar,
+    _multi: MultiProgress,
+}
+
+impl ProgressObserver {
+    /// Create the progress display; when `visible` is false nothing is drawn.
+    fn new(visible: bool) -> Self {
+        let multi = if visible {
+            MultiProgress::new()
+        } else {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        };
+
+        let bar = multi.add(ProgressBar::new(0));
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} files",
+            )
+            .unwrap()
+            .progress_chars("#>-"),
+        );
+
+        let current = multi.add(ProgressBar::new_spinner());
+        current.set_style(
+            ProgressStyle::with_template("{spinner:.green} Hashing: {msg}")
+                .unwrap()
+                .tick_chars("/-\\| "),
+        );
+        current.enable_steady_tick(Duration::from_millis(100));
+
+        Self {
+            bar,
+            current,
+            _multi: multi,
+        }
+    }
+
+    fn finish(&self) {
+        self.bar.finish_with_message("Hashing complete");
+        self.current.finish_with_message("Hashing complete");
+    }
+}
+
+impl ScanObserver for ProgressObserver {
+    fn walk_finished(&mut self, total_files: usize) {
+        self.bar.set_length(total_files as u64);
+    }
+
+    fn hashing_started(&mut self, path: &Path) {
+        self.current.set_message(path.display().to_string());
+        // Count the file up front so unreadable files still advance the bar.
+        self.bar.inc(1);
+    }
+
+    fn error(&mut self, path: &Path, error: &io::Error) {
+        self.bar.suspend(|| {
+            eprintln!(
+                "{} {}: {}",
+                "[WARN]".yellow().bold(),
+                path.display(),
+                error.to_string().yellow()
+            )
+        });
+    }
 }
 
 /// Ask the user a yes/no question. Returns `true` for "y"/"yes" (case-insensitive).
@@ -71,17 +522,10 @@ fn ask_yes_no(prompt: &str) -> bool {
     print!("{prompt} ");
     io::stdout().flush().ok();
 
-    let mut input = String::new();
-    match io::stdin().read_line(&mut input) {
-        Ok(_) => {
-            let answer = input.trim();
-            answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
-        }
-        Err(e) => {
-            eprintln!("{} {}", "Failed to read input:".red(), e);
-            false
-        }
-    }
+    read_answer().is_some_and(|input| {
+        let answer = input.trim();
+        answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
+    })
 }
 
 /// Ask the user whether they want to proceed with deletion.
@@ -98,58 +542,130 @@ fn ask_user_to_confirm() -> bool {
     ask_yes_no(&prompt)
 }
 
-/// Prompt the user to select an index in the inclusive range [1, max],
-/// or choose to keep all copies. Empty input defaults to 1.
-fn prompt_for_selection(max: usize) -> Option<usize> {
+/// An answer to the per-group prompt of interactive mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Choice {
+    /// Keep the file at this (zero-based) position and delete the others.
+    Keep(usize),
+    /// Keep every copy.
+    KeepAll,
+    /// Leave the group undecided; nothing in it is deleted.
+    Skip,
+    /// Go back to the previous group.
+    Back,
+    /// Stop asking and apply the decisions made so far.
+    Quit,
+    /// From now on, keep the copy under this directory when a group has one.
+    Rule(PathBuf),
+}
+
+/// Parse a line typed at the interactive prompt for a group of `max` files.
+/// Empty input keeps all copies. Returns `None` for anything else not
+/// understood.
+fn parse_choice(input: &str, max: usize) -> Option<Choice> {
+    let trimmed = input.trim();
+    let choice = match trimmed.to_ascii_lowercase().as_str() {
+        "" | "a" | "all" => Choice::KeepAll,
+        "s" | "skip" => Choice::Skip,
+        "b" | "back" => Choice::Back,
+        "q" | "quit" => Choice::Quit,
+        _ => {
+            if let Some(dir) = trimmed.strip_prefix("r ").map(str::trim)
+                && !dir.is_empty()
+            {
+                return Some(Choice::Rule(PathBuf::from(dir)));
+            }
+            let num = trimmed.parse::<usize>().ok()?;
+            if num < 1 || num > max {
+                return None;
+            }
+            Choice::Keep(num - 1)
+        }
+    };
+    Some(choice)
+}
+
+/// Set while a prompt waits for input. There is no current file to finish
+/// then, and a pending read cannot be cancelled, so Ctrl-C exits straight away.
+static AT_PROMPT: AtomicBool = AtomicBool::new(false);
+
+/// Read one line from stdin; `None` if it could not be read.
+fn read_answer() -> Option<String> {
+    let mut input = String::new();
+    AT_PROMPT.store(true, Ordering::SeqCst);
+    let result = io::stdin().read_line(&mut input);
+    AT_PROMPT.store(false, Ordering::SeqCst);
+    match result {
+        Ok(_) => Some(input),
+        Err(e) => {
+            eprintln!("{} {}", "Failed to read input:".red(), e);
+            None
+        }
+    }
+}
+
+/// Prompt the user about a group of `max` files until they give a valid
+/// answer. Empty input, or input that cannot be read, keeps all copies.
+fn prompt_for_choice(max: usize) -> Choice {
     loop {
         print!(
             "{}\n{} ",
-            "Enter a number to keep that file or 'a' to keep all copies.".bright_red(),
-            format!("Which file should be kept? Enter 1-{} (default 1):", max)
+            "Enter a number to keep that file, 'a' to keep all copies, 's' to skip, 'b' to go back,\n\
+             'r DIR' to always keep the copy under DIR, or 'q' to stop and apply the choices so far."
+                .bright_red(),
+            format!("Which file should be kept? Enter 1-{max}, a, s, b, r or q (default a):")
                 .bright_red()
                 .bold()
         );
         io::stdout().flush().ok();
 
-        let mut input = String::new();
-        if io::stdin().read_line(&mut input).is_err() {
-            eprintln!(
-                "{}",
-                "Failed to read input, defaulting to A (keep all).".yellow()
+        let Some(input) = read_answer() else {
+            return Choice::KeepAll;
+        };
+        if input.trim().eq_ignore_ascii_case("r") {
+            print!(
+                "{} ",
+                "Keep the copy under which directory?".bright_red().bold()
             );
-            return None;
-        }
-        let trimmed = input.trim();
-        if trimmed.is_empty() {
-            return None;
-        }
-        if trimmed.eq_ignore_ascii_case("a") || trimmed.eq_ignore_ascii_case("all") {
-            return None;
+            io::stdout().flush().ok();
+            match read_answer() {
+                Some(dir) if !dir.trim().is_empty() => {
+                    return Choice::Rule(PathBuf::from(dir.trim()));
+                }
+                _ => continue,
+            }
         }
-        if let Ok(num) = trimmed.parse::<usize>()
-            && num >= 1
-            && num <= max
-        {
-            return Some(num);
+        if let Some(choice) = parse_choice(&input, max) {
+            return choice;
         }
         eprintln!(
             "{}",
-            format!("Please enter a number between 1 and {}.", max)
+            format!("Please enter a number between 1 and {max}, or a, s, b, r or q.")
                 .yellow()
                 .bold()
         );
     }
 }
 
-/// Delete a single file path, returning the number of bytes freed if successful.
-fn delete_path(path: &Path) -> Option<u64> {
+/// What happened to a path we tried to delete.
+enum DeleteOutcome {
+    /// Removed, freeing this many bytes.
+    Deleted(u64),
+    /// Removing it failed; it is still there.
+    Failed(io::Error),
+    /// It was gone or unreadable by the time we got to it.
+    Skipped,
+}
+
+/// Delete a single file path, printing what happened.
+fn delete_path(path: &Path) -> DeleteOutcome {
     match fs::metadata(path) {
         Ok(meta) => {
             let size = meta.len();
             match fs::remove_file(path) {
                 Ok(_) => {
                     println!("{} {}", "[DELETED]".red().bold(), path.display());
-                    Some(size)
+                    DeleteOutcome::Deleted(size)
                 }
                 Err(e) => {
                     eprintln!(
@@ -158,7 +674,7 @@ fn delete_path(path: &Path) -> Option<u64> {
                         path.display(),
                         e.to_string().red()
                     );
-                    None
+                    DeleteOutcome::Failed(e)
                 }
             }
         }
@@ -169,247 +685,1560 @@ fn delete_path(path: &Path) -> Option<u64> {
                 "[SKIPPED]".yellow().bold(),
                 path.display().to_string().yellow()
             );
-            None
+            DeleteOutcome::Skipped
+        }
+    }
+}
+
+/// Runs deletions, stopping early on Ctrl-C, once too many have failed, or
+/// once the audit log can no longer be written.
+struct Deleter<'a> {
+    cancel: &'a CancellationToken,
+    failures: FailureBreaker,
+    audit: Option<AuditLog>,
+    /// First audit log write error. Nothing is deleted after one.
+    audit_error: Option<io::Error>,
+}
+
+impl<'a> Deleter<'a> {
+    /// Stop once `cancel` is triggered or after `max_failures` failures.
+    fn new(cancel: &'a CancellationToken, max_failures: Option<u64>) -> Self {
+        Self {
+            cancel,
+            failures: FailureBreaker::new(max_failures),
+            audit: None,
+            audit_error: None,
+        }
+    }
+
+    /// Record every outcome in `log`.
+    fn with_audit_log(mut self, log: AuditLog) -> Self {
+        self.audit = Some(log);
+        self
+    }
+
+    /// Whether to stop before the next deletion.
+    fn should_stop(&self) -> bool {
+        self.cancel.is_cancelled() || self.failures.tripped() || self.audit_error.is_some()
+    }
+
+    /// Delete one file that duplicates `keep`, returning the number of bytes
+    /// freed if successful.
+    fn delete_file(&mut self, path: &Path, hash: &str, keep: &Path) -> Option<u64> {
+        let outcome = delete_path(path);
+        self.audit(&outcome, AuditKind::File, path, hash, keep);
+        match outcome {
+            DeleteOutcome::Deleted(size) => Some(size),
+            DeleteOutcome::Failed(_) => {
+                self.failures.record_failure();
+                None
+            }
+            DeleteOutcome::Skipped => None,
+        }
+    }
+
+    /// Remove the compared files of `dir`, a redundant copy in `group`, then
+    /// the directories that leaves empty. Anything the scan never compared
+    /// stays, and so does every directory still holding something.
+    ///
+    /// Returns the number of files removed and the bytes freed.
+    fn delete_dir(&mut self, dir: &Path, group: &DirectoryGroup) -> (u64, u64) {
+        let mut deleted = Vec::new();
+        let mut deleted_bytes = 0u64;
+        for (path, hash, keep) in group.planned_files(dir) {
+            if self.should_stop() {
+                break;
+            }
+            if let Some(size) = self.delete_file(&path, hash, &keep) {
+                deleted.push(path);
+                deleted_bytes += size;
+            }
+        }
+
+        if let Some(parent) = dir.parent() {
+            ddupe::prune_empty_dirs(&deleted, &[parent.to_path_buf()]);
+        }
+        if dir.exists() {
+            if !self.should_stop() {
+                println!(
+                    "{} {} {}",
+                    "[KEPT DIR]".yellow().bold(),
+                    dir.display().to_string().cyan(),
+                    "(still holds files that were not compared)".yellow()
+                );
+            }
+        } else {
+            println!("{} {}", "[DELETED DIR]".red().bold(), dir.display());
+            let outcome = DeleteOutcome::Deleted(deleted_bytes);
+            self.audit(
+                &outcome,
+                AuditKind::Directory,
+                dir,
+                &group.signature,
+                &group.keep,
+            );
+        }
+        (deleted.len() as u64, deleted_bytes)
+    }
+
+    /// Append `outcome` to the audit log, if there is one.
+    fn audit(
+        &mut self,
+        outcome: &DeleteOutcome,
+        kind: AuditKind,
+        path: &Path,
+        hash: &str,
+        keep: &Path,
+    ) {
+        let Some(log) = self.audit.as_mut() else {
+            return;
+        };
+        if self.audit_error.is_some() {
+            return;
+        }
+        let action = match outcome {
+            DeleteOutcome::Deleted(_) => AuditAction::Deleted,
+            DeleteOutcome::Failed(_) => AuditAction::Failed,
+            DeleteOutcome::Skipped => AuditAction::Skipped,
+        };
+        let mut entry = log.entry(action, kind, path, hash, keep);
+        match outcome {
+            DeleteOutcome::Deleted(size) => entry.size = Some(*size),
+            DeleteOutcome::Failed(e) => entry.error = Some(e.to_string()),
+            DeleteOutcome::Skipped => {}
+        }
+        if let Err(e) = log.record(&entry) {
+            self.audit_error = Some(e);
         }
     }
 }
 
-/// Delete the given list of files, reporting progress and total savings.
+/// Delete the dupes of every group, reporting progress and total savings.
+///
+/// Stops before the next file once the deleter says so.
 ///
 /// Returns:
 /// - number of successfully deleted files
 /// - total number of bytes freed
-fn delete_files(paths: &[PathBuf]) -> (u64, u64) {
+fn delete_files(groups: &[DuplicateGroup], deleter: &mut Deleter) -> (u64, u64) {
     println!("{}", "Deleting duplicate files...".red().bold());
 
     let mut deleted_count = 0u64;
     let mut deleted_bytes = 0u64;
 
-    for path in paths {
-        if let Some(size) = delete_path(path) {
-            deleted_count += 1;
-            deleted_bytes += size;
+    for group in groups {
+        for path in &group.dupes {
+            if deleter.should_stop() {
+                return (deleted_count, deleted_bytes);
+            }
+            if let Some(size) = deleter.delete_file(path, &group.hash, &group.keep) {
+                deleted_count += 1;
+                deleted_bytes += size;
+            }
         }
     }
 
     (deleted_count, deleted_bytes)
 }
 
-/// Interactively ask the user about each duplicate before deleting it.
-///
-/// Returns the same tuple as `delete_files`.
-fn delete_files_interactively(groups: &[ddupe::DuplicateGroup]) -> (u64, u64) {
-    println!(
-        "{}",
-        "Interactive mode: decide for each duplicate individually."
-            .red()
-            .bold()
-    );
-
-    let mut deleted_count = 0u64;
-    let mut deleted_bytes = 0u64;
-
-    for (idx, group) in groups.iter().enumerate() {
-        let mut candidates = Vec::new();
-        candidates.push(group.keep.clone());
-        candidates.extend(group.dupes.iter().cloned());
+/// The position of the first candidate under one of the `rules` directories,
+/// trying the rules in the order they were recorded.
+fn rule_match(rules: &[PathBuf], candidates: &[PathBuf]) -> Option<usize> {
+    let absolute = |p: &Path| std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+    rules.iter().find_map(|dir| {
+        let dir = absolute(dir);
+        candidates
+            .iter()
+            .position(|p| absolute(p).starts_with(&dir))
+    })
+}
 
-        println!(
-            "\n{} {} {}",
-            "---".bright_yellow(),
-            "Duplicate Group".bright_yellow().bold(),
-            (idx + 1).to_string().bright_yellow()
-        );
+/// Decide which file to keep in each group, returning its position among the
+/// group's files (default keep first) or `None` to delete nothing.
+///
+/// `ask` is called with the group's position and its files. Groups with a copy
+/// under a directory recorded by [`Choice::Rule`] are decided without asking,
+/// unless the user went back to them. Nothing is deleted here.
+fn decide_groups(
+    groups: &[DuplicateGroup],
+    mut ask: impl FnMut(usize, &[PathBuf]) -> Choice,
+) -> Vec<Option<usize>> {
+    let mut decisions = vec![None; groups.len()];
+    let mut rules: Vec<PathBuf> = Vec::new();
+    let mut revisiting = false;
+    let mut idx = 0;
 
-        for (i, path) in candidates.iter().enumerate() {
-            let default_hint = if i == 0 { " (default)" } else { "" };
-            println!(
-                "  [{}] {}{}",
-                (i + 1).to_string().bright_yellow(),
-                path.display().to_string().cyan(),
-                default_hint
-            );
-        }
-        println!(
-            "  [{}] {}",
-            "A".bright_yellow(),
-            "Keep all copies (skip deletion)".cyan()
-        );
+    while idx < groups.len() {
+        let candidates: Vec<PathBuf> = std::iter::once(&groups[idx].keep)
+            .chain(&groups[idx].dupes)
+            .cloned()
+            .collect();
 
-        let selection = prompt_for_selection(candidates.len());
-        if selection.is_none() {
+        if !revisiting && let Some(keep_idx) = rule_match(&rules, &candidates) {
             println!(
-                "{}",
-                "[KEEPING ALL] Chose to keep every file in this group."
-                    .green()
-                    .bold()
+                "{} {}",
+                "[RULE] Keeping".green().bold(),
+                candidates[keep_idx].display().to_string().cyan()
             );
+            decisions[idx] = Some(keep_idx);
+            idx += 1;
             continue;
         }
-        let keep_idx = selection.unwrap() - 1;
-
-        println!(
-            "{} {}",
-            "[KEEPING]".green().bold(),
-            candidates[keep_idx].display().to_string().cyan()
-        );
+        revisiting = false;
 
-        for (i, path) in candidates.iter().enumerate() {
-            if i == keep_idx {
-                continue;
+        match ask(idx, &candidates) {
+            Choice::Keep(keep_idx) => {
+                println!(
+                    "{} {}",
+                    "[KEEPING]".green().bold(),
+                    candidates[keep_idx].display().to_string().cyan()
+                );
+                decisions[idx] = Some(keep_idx);
+                idx += 1;
             }
-            if let Some(size) = delete_path(path) {
-                deleted_count += 1;
-                deleted_bytes += size;
+            Choice::KeepAll => {
+                println!(
+                    "{}",
+                    "[KEEPING ALL] Chose to keep every file in this group."
+                        .green()
+                        .bold()
+                );
+                decisions[idx] = None;
+                idx += 1;
+            }
+            Choice::Skip => {
+                println!(
+                    "{}",
+                    "[SKIPPED] Nothing in this group will be deleted.".yellow()
+                );
+                decisions[idx] = None;
+                idx += 1;
+            }
+            Choice::Back if idx == 0 => {
+                println!("{}", "Already at the first group.".yellow());
+            }
+            Choice::Back => {
+                idx -= 1;
+                revisiting = true;
+            }
+            Choice::Quit => {
+                println!(
+                    "{}",
+                    "[QUIT] Applying the choices made so far; later groups are kept."
+                        .yellow()
+                        .bold()
+                );
+                break;
+            }
+            Choice::Rule(dir) => {
+                println!(
+                    "{} {}",
+                    "[RULE] From now on keeping the copy under".green().bold(),
+                    dir.display().to_string().cyan()
+                );
+                rules.push(dir);
             }
         }
     }
 
-    (deleted_count, deleted_bytes)
+    decisions
 }
 
-/// Write a JSON report to disk without deleting or prompting.
-fn write_json_report(
-    output_path: &Path,
-    roots: &[PathBuf],
-    analysis: &ddupe::DuplicateAnalysis,
-    interactive: bool,
-) -> io::Result<()> {
-    if let Some(parent) = output_path.parent()
-        && !parent.as_os_str().is_empty()
-    {
-        fs::create_dir_all(parent)?;
+/// Size and modification time of `path`, for the interactive listing.
+fn describe_file(path: &Path) -> String {
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            let modified = metadata
+                .modified()
+                .map(ddupe::report::format_timestamp)
+                .unwrap_or_else(|_| "unknown".to_string());
+            format!("{}, modified {}", format_bytes(metadata.len()), modified)
+        }
+        Err(e) => format!("unreadable: {e}"),
     }
-
-    let groups = analysis
-        .groups
-        .iter()
-        .map(|g| JsonGroup {
-            files: std::iter::once(&g.keep)
-                .chain(g.dupes.iter())
-                .map(|p| p.display().to_string())
-                .collect(),
-        })
-        .collect();
-
-    let report = JsonReport {
-        roots: roots.iter().map(|r| r.display().to_string()).collect(),
-        duplicate_groups: groups,
-        removable_count: analysis.total_dupes(),
-        savings_bytes: analysis.total_saving_bytes,
-        dry_run: true,
-        interactive,
-        mode: "json",
-    };
-
-    let mut file = std::fs::File::create(output_path)?;
-    serde_json::to_writer_pretty(&mut file, &report).map_err(io::Error::other)
 }
 
-fn main() {
-    // Parse command-line arguments using clap.
-    let args = Args::parse();
-    let root = args.path;
-    let roots = vec![root.clone()];
-    let json_mode = args.json_output.is_some();
-
+/// Print one group's files for the interactive prompt.
+fn print_interactive_group(idx: usize, candidates: &[PathBuf]) {
     println!(
-        "{}\nLicense: LGPL-3.0-or-later\nSource: https://github.com/Morrolan/ddupe\nDocs:   https://morrolan.github.io/ddupe\n------------------------------------------------------------",
-        "ddupe — Duplicate File Cleaner".bold()
+        "\n{} {} {}",
+        "---".bright_yellow(),
+        "Duplicate Group".bright_yellow().bold(),
+        (idx + 1).to_string().bright_yellow()
     );
 
-    // Basic sanity check: ensure the directory exists.
-    if !root.exists() {
-        eprintln!(
+    for (i, path) in candidates.iter().enumerate() {
+        let default_hint = if i == 0 { " (default keep)" } else { "" };
+        println!(
+            "  [{}] {} ({}){}",
+            (i + 1).to_string().bright_yellow(),
+            path.display().to_string().cyan(),
+            describe_file(path).dimmed(),
+            default_hint
+        );
+    }
+    println!(
+        "  [{}] {}",
+        "A".bright_yellow(),
+        "Keep all copies (skip deletion)".cyan()
+    );
+}
+
+/// Interactively ask the user which file to keep in each group, then delete
+/// the others once every group has been decided (or the user quits).
+///
+/// Returns the same tuple as `delete_files`.
+fn delete_files_interactively(
+    groups: &[DuplicateGroup],
+    protect: &ProtectedPaths,
+    deleter: &mut Deleter,
+) -> (u64, u64) {
+    println!(
+        "{}",
+        "Interactive mode: decide for each duplicate group, then the choices are applied."
+            .red()
+            .bold()
+    );
+
+    let decisions = decide_groups(groups, |idx, candidates| {
+        print_interactive_group(idx, candidates);
+        prompt_for_choice(candidates.len())
+    });
+
+    let mut deleted_count = 0u64;
+    let mut deleted_bytes = 0u64;
+
+    if decisions.iter().any(Option::is_some) {
+        println!("\n{}", "Deleting duplicate files...".red().bold());
+    }
+    for (group, decision) in groups.iter().zip(decisions) {
+        let Some(keep_idx) = decision else {
+            continue;
+        };
+        let candidates: Vec<&PathBuf> = std::iter::once(&group.keep).chain(&group.dupes).collect();
+        for (i, path) in candidates.iter().enumerate() {
+            if deleter.should_stop() {
+                return (deleted_count, deleted_bytes);
+            }
+            if i == keep_idx {
+                continue;
+            }
+            if protect.is_protected(path) {
+                println!(
+                    "{} {}",
+                    "[PROTECTED]".blue().bold(),
+                    path.display().to_string().cyan()
+                );
+                continue;
+            }
+            if let Some(size) = deleter.delete_file(path, &group.hash, candidates[keep_idx]) {
+                deleted_count += 1;
+                deleted_bytes += size;
+            }
+        }
+    }
+
+    (deleted_count, deleted_bytes)
+}
+
+/// Remove the redundant copies in each directory group: the files the scan
+/// compared, then any directories left empty. With `ask`, the user confirms
+/// each group first.
+///
+/// Returns the number of files removed and the bytes freed, like
+/// `delete_files`.
+fn delete_directories(groups: &[DirectoryGroup], deleter: &mut Deleter, ask: bool) -> (u64, u64) {
+    let mut deleted_count = 0u64;
+    let mut deleted_bytes = 0u64;
+
+    for (idx, group) in groups.iter().enumerate() {
+        if deleter.should_stop() {
+            break;
+        }
+        if ask {
+            print_directory_group(idx, group);
+            let prompt = format!(
+                "{}",
+                "Remove the [DUPE DIR] directories? [y/N]:"
+                    .bright_red()
+                    .bold()
+            );
+            if !ask_yes_no(&prompt) {
+                continue;
+            }
+        }
+        for dir in &group.dupes {
+            if deleter.should_stop() {
+                break;
+            }
+            let (count, bytes) = deleter.delete_dir(dir, group);
+            deleted_count += count;
+            deleted_bytes += bytes;
+        }
+    }
+
+    (deleted_count, deleted_bytes)
+}
+
+/// Print one directory group with KEEP/DUPE markers.
+fn print_directory_group(idx: usize, group: &DirectoryGroup) {
+    println!(
+        "\n{} {} {} ({} file(s), {} each)",
+        "---".bright_yellow(),
+        "Duplicate Directory Group".bright_yellow().bold(),
+        (idx + 1).to_string().bright_yellow(),
+        group.files,
+        format_bytes(group.size)
+    );
+    println!(
+        "{} {}",
+        "[KEEP DIR]".green().bold(),
+        group.keep.display().to_string().cyan()
+    );
+    for dupe in &group.dupes {
+        println!(
+            "{} {}",
+            "[DUPE DIR]".red().bold(),
+            dupe.display().to_string().cyan()
+        );
+    }
+}
+
+/// Print the `--show-mounts` line for one duplicate group.
+fn print_group_mounts(mounts: &[PathBuf]) {
+    let list = mounts
+        .iter()
+        .map(|m| m.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if mounts.len() > 1 {
+        println!(
+            "{} {} {}",
+            "[MOUNT]".blue().bold(),
+            list.cyan(),
+            "(different filesystems: cannot be hard-linked)".yellow()
+        );
+    } else {
+        println!("{} {}", "[MOUNT]".blue().bold(), list.cyan());
+    }
+}
+
+/// Print every directory group found by `--dirs`.
+fn print_directory_groups(groups: &[DirectoryGroup]) {
+    println!("\n{}", "Duplicate directories found:".yellow().bold());
+    for (idx, group) in groups.iter().enumerate() {
+        print_directory_group(idx, group);
+    }
+}
+
+/// How many rows each `--stats` table shows.
+const STATS_ROWS: usize = 10;
+
+/// Print one `--stats` table of reclaimable space.
+fn print_waste_table(title: &str, entries: &[WasteEntry]) {
+    println!("\n{}", title.bright_yellow().bold());
+    for entry in entries.iter().take(STATS_ROWS) {
+        println!(
+            "  {:>12}  {:>6} file(s)  {}",
+            format_bytes(entry.bytes).bright_green(),
+            entry.files,
+            entry.key.cyan()
+        );
+    }
+}
+
+/// Print the `--stats` summary.
+fn print_stats(stats: &WasteStats) {
+    println!("\n{}", "Waste statistics:".blue().bold());
+    print_waste_table("By top-level directory", &stats.by_directory);
+    print_waste_table("By extension", &stats.by_extension);
+    print_waste_table("By size band", &stats.by_size_band);
+
+    println!(
+        "\n{}",
+        "Directory pairs sharing the most content"
+            .bright_yellow()
+            .bold()
+    );
+    for pair in stats.shared_directories.iter().take(STATS_ROWS) {
+        println!(
+            "  {:>12}  {:>6} group(s)  {} {} {}",
+            format_bytes(pair.bytes).bright_green(),
+            pair.groups,
+            pair.first.display().to_string().cyan(),
+            "<->".dimmed(),
+            pair.second.display().to_string().cyan()
+        );
+    }
+}
+
+/// Install a Ctrl-C handler that cancels `token`.
+///
+/// The first interrupt asks the running scan or deletion to stop after the
+/// current file; a second one exits immediately. At a confirmation prompt,
+/// where nothing is in progress, the first one exits immediately.
+fn install_interrupt_handler(token: &CancellationToken) {
+    let token = token.clone();
+    let result = ctrlc::set_handler(move || {
+        if AT_PROMPT.load(Ordering::SeqCst) {
+            eprintln!();
+            std::process::exit(130);
+        }
+        if token.is_cancelled() {
+            std::process::exit(130);
+        }
+        token.cancel();
+        eprintln!(
+            "\n{}",
+            "Interrupted: finishing the current file, press Ctrl-C again to abort immediately."
+                .yellow()
+                .bold()
+        );
+    });
+    if let Err(e) = result {
+        eprintln!(
+            "{} {}",
+            "Warning: could not install Ctrl-C handler:".yellow(),
+            e
+        );
+    }
+}
+
+/// Open the `--audit-log` file, refusing to run (exit 1) if that fails.
+fn open_audit_log_or_exit(path: &Path) -> AuditLog {
+    AuditLog::open(path).unwrap_or_else(|e| {
+        eprintln!(
+            "{} {}",
+            "Refusing to run: cannot open audit log:".red().bold(),
+            e.to_string().red()
+        );
+        std::process::exit(1);
+    })
+}
+
+/// Stop (exit 1) once the audit log could not be written.
+fn exit_on_audit_error(deleter: &Deleter) {
+    if let Some(e) = deleter.audit_error.as_ref() {
+        eprintln!(
+            "{} {}",
+            "Stopped: could not write the audit log:".red().bold(),
+            e.to_string().red()
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Stop (exit 1) once deletions failed `--max-failures` times.
+fn exit_on_max_failures(deleter: &Deleter) {
+    if deleter.failures.tripped() {
+        eprintln!(
+            "{} {}",
+            "Stopped:".red().bold(),
+            format!(
+                "{} deletion(s) failed, reaching --max-failures.",
+                deleter.failures.failures()
+            )
+            .red()
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Print the end-of-run deletion summary, noting if it was cut short.
+fn print_deletion_summary(deleted_count: u64, deleted_bytes: u64, cancel: &CancellationToken) {
+    let label = if cancel.is_cancelled() {
+        "Interrupted:".yellow().bold()
+    } else {
+        "Done:".green().bold()
+    };
+    println!(
+        "\n{} Deleted {} file(s), freeing approximately {}.",
+        label,
+        deleted_count.to_string().bright_yellow(),
+        format_bytes(deleted_bytes).bright_green().bold()
+    );
+}
+
+/// Report the directories removed by `--prune-empty-dirs`.
+fn print_pruned_dirs(pruned: &[PathBuf]) {
+    for dir in pruned {
+        println!("{} {}", "[PRUNED]".red().bold(), dir.display());
+    }
+    println!(
+        "{} Removed {} empty director(ies).",
+        "Done:".green().bold(),
+        pruned.len().to_string().bright_yellow()
+    );
+}
+
+/// Create a report file, including any missing parent directories.
+fn create_report_file(output_path: &Path) -> io::Result<fs::File> {
+    if let Some(parent) = output_path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+    fs::File::create(output_path)
+}
+
+/// Write a report with `write`, printing where it went or exiting on failure.
+fn write_report_or_exit(kind: &str, output_path: &Path, write: impl FnOnce() -> io::Result<()>) {
+    if let Err(e) = write() {
+        eprintln!(
+            "{} {}",
+            format!("Failed to write {kind} report:").red().bold(),
+            e.to_string().red()
+        );
+        std::process::exit(1);
+    }
+    println!(
+        "{} {}",
+        format!("{kind} report written to:").blue().bold(),
+        output_path.display().to_string().cyan()
+    );
+}
+
+/// Write a CSV report to disk without deleting or prompting.
+fn write_csv_report(output_path: &Path, analysis: &ddupe::DuplicateAnalysis) -> io::Result<()> {
+    let file = create_report_file(output_path)?;
+    ddupe::report::write_csv(io::BufWriter::new(file), analysis)
+}
+
+/// Write an HTML report to disk without deleting or prompting.
+fn write_html_report(
+    output_path: &Path,
+    analysis: &ddupe::DuplicateAnalysis,
+    context: &ReportContext,
+) -> io::Result<()> {
+    let file = create_report_file(output_path)?;
+    ddupe::report::write_html(io::BufWriter::new(file), analysis, context)
+}
+
+/// Write a JSON report to disk without deleting or prompting.
+fn write_json_report(
+    output_path: &Path,
+    analysis: &ddupe::DuplicateAnalysis,
+    context: &ReportContext,
+) -> io::Result<()> {
+    let file = create_report_file(output_path)?;
+    ddupe::report::write_json(io::BufWriter::new(file), analysis, context)
+}
+
+/// Options that only make sense on the command line.
+const CLI_ONLY_OPTIONS: [&str; 3] = ["profile", "no-config", "no-dry-run"];
+
+/// Safety lists: values from the configuration are added to those given on
+/// the command line, never replaced by them.
+const ADDITIVE_OPTIONS: [&str; 1] = ["protect"];
+
+/// Turn configuration settings into extra command-line arguments.
+///
+/// Keys are long option names. Options already given on the command line win,
+/// and so do options given there that cannot be combined with a setting (so
+/// `--json-output` drops a configured `porcelain`, and `--no-dry-run` a
+/// configured `dry-run`). `protect` is the exception: configured patterns
+/// always apply, on top of any given on the command line. `false` leaves a
+/// flag unset, and arrays repeat the option once per item.
+fn config_args(
+    command: &Command,
+    matches: &ArgMatches,
+    settings: &toml::Table,
+) -> Result<Vec<OsString>, String> {
+    let on_command_line: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect();
+    let mut extra = Vec::new();
+    for (key, value) in settings {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.as_str()))
+            .filter(|_| !CLI_ONLY_OPTIONS.contains(&key.as_str()))
+            .ok_or_else(|| format!("unknown option '{key}'"))?;
+        let conflicts = command.get_arg_conflicts_with(arg);
+        let overridden = on_command_line.iter().any(|given| {
+            given.get_id() == arg.get_id()
+                || conflicts.iter().any(|c| c.get_id() == given.get_id())
+                || command
+                    .get_arg_conflicts_with(given)
+                    .iter()
+                    .any(|c| c.get_id() == arg.get_id())
+        });
+        if overridden && !ADDITIVE_OPTIONS.contains(&key.as_str()) {
+            continue;
+        }
+
+        if !arg.get_action().takes_values() {
+            match value {
+                toml::Value::Boolean(true) => extra.push(format!("--{key}").into()),
+                toml::Value::Boolean(false) => {}
+                _ => return Err(format!("option '{key}' must be true or false")),
+            }
+            continue;
+        }
+        let values = match value {
+            toml::Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                    value.to_string()
+                }
+                _ => return Err(format!("option '{key}' has an unsupported value")),
+            };
+            extra.push(format!("--{key}={value}").into());
+        }
+    }
+    Ok(extra)
+}
+
+/// Parse the command line, filling in options it leaves unset from the
+/// configuration files and the `--profile` it selects.
+fn parse_args() -> Args {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let command = Args::command();
+    let matches = command.clone().get_matches_from(&argv);
+    if matches.get_flag("no_config") {
+        return Args::parse_from(argv);
+    }
+
+    let profile = matches.get_one::<String>("profile").map(String::as_str);
+    let extra = Config::discover()
+        .and_then(|config| config.settings(profile))
+        .map_err(|e| e.to_string())
+        .and_then(|settings| config_args(&command, &matches, &settings));
+    match extra {
+        Ok(extra) => {
+            let mut argv = argv.into_iter();
+            Args::parse_from(argv.next().into_iter().chain(extra).chain(argv))
+        }
+        Err(e) => {
+            eprintln!(
+                "{} {}",
+                "Configuration error:".red().bold(),
+                e.to_string().red()
+            );
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Write the `--manifest-output` file, exiting on failure.
+fn write_manifest_or_exit(
+    path: &Path,
+    format: ManifestFormat,
+    entries: &[ManifestEntry],
+    quiet: bool,
+) {
+    // Absolute paths still name the same files wherever the manifest is read.
+    let entries: Vec<ManifestEntry> = entries
+        .iter()
+        .map(|entry| ManifestEntry {
+            path: std::path::absolute(&entry.path).unwrap_or_else(|_| entry.path.clone()),
+            ..entry.clone()
+        })
+        .collect();
+    let result = create_report_file(path).and_then(|file| {
+        let mut writer = io::BufWriter::new(file);
+        manifest::write_manifest(&mut writer, format, &entries)?;
+        writer.flush()
+    });
+    if let Err(e) = result {
+        eprintln!(
+            "{} {}",
+            "Failed to write manifest:".red().bold(),
+            e.to_string().red()
+        );
+        std::process::exit(1);
+    }
+    if !quiet {
+        println!(
+            "{} {} file(s) to {}",
+            "Manifest written:".green().bold(),
+            entries.len(),
+            path.display()
+        );
+    }
+}
+
+/// Record a finished scan of `roots` in the `--index` file. A failure is
+/// reported but does not stop the run.
+fn update_index(path: &Path, roots: &[PathBuf], recorder: IndexRecorder, quiet: bool) {
+    let result = ContentIndex::load_or_new(path).and_then(|mut index| {
+        index.update(roots, recorder);
+        index.save(path).map(|()| index.len())
+    });
+    match result {
+        Ok(files) if !quiet => println!(
+            "{} {} file(s) in {}",
+            "Index updated:".green().bold(),
+            files,
+            path.display()
+        ),
+        Ok(_) => {}
+        Err(e) => eprintln!(
+            "{} {}: {}",
+            "Warning: could not update the index".yellow().bold(),
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// `ddupe query`: look files up in the `--index` without rescanning. Exits
+/// with 0 if every file has an unchanged indexed copy, 1 if any has none.
+fn run_query(args: &Args, query: &QueryArgs) {
+    if let Some(path) = args.path.as_ref() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "'{}' cannot be given before `query`; list the files after it",
+                    path.display()
+                ),
+            )
+            .exit();
+    }
+    let Some(index_path) = args.index.as_ref() else {
+        eprintln!(
             "{} {}",
             "Error:".red().bold(),
-            format!("'{}' does not exist.", root.display()).red()
+            "no index to query: pass --index FILE or set `index` in ddupe.toml.".red()
+        );
+        std::process::exit(2);
+    };
+    let index = match ContentIndex::load(index_path) {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!(
+                "{} {}: {}",
+                "Cannot read index".red().bold(),
+                index_path.display(),
+                e.to_string().red()
+            );
+            std::process::exit(2);
+        }
+    };
+
+    let mut all_found = true;
+    for file in &query.files {
+        let result = match index.query(file) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!(
+                    "{} {}: {}",
+                    "Error:".red().bold(),
+                    file.display(),
+                    e.to_string().red()
+                );
+                std::process::exit(2);
+            }
+        };
+        all_found &= result.found();
+
+        if query.json {
+            println!(
+                "{}",
+                serde_json::to_string(&result).expect("query results serialise")
+            );
+            continue;
+        }
+        if result.copies.is_empty() {
+            println!(
+                "{} {}",
+                "[NEW]".green().bold(),
+                file.display().to_string().cyan()
+            );
+            continue;
+        }
+        println!(
+            "{} {} {}",
+            "[FOUND]".red().bold(),
+            file.display().to_string().cyan(),
+            format!("({})", format_bytes(result.size)).dimmed()
+        );
+        for copy in &result.copies {
+            let note = match copy.status {
+                CopyStatus::Current => String::new(),
+                CopyStatus::Changed => " (changed since indexed)".yellow().to_string(),
+                CopyStatus::Missing => " (missing)".yellow().to_string(),
+            };
+            println!("  {}{}", copy.path.display(), note);
+        }
+    }
+
+    if !all_found {
+        std::process::exit(1);
+    }
+}
+
+/// Whether the platform's watcher reports files being closed after writing
+/// (inotify does). Elsewhere, watch mode waits for writes to settle instead.
+const REPORTS_CLOSE_WRITE: bool = cfg!(target_os = "linux");
+
+/// How long a file must go unwritten before watch mode checks it, where files
+/// being closed are not reported.
+const WATCH_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// What `watch --delete` may remove: unprotected files under the watched
+/// roots, up to the deletion limits counted over the whole session.
+struct WatchPolicy<'a> {
+    delete: bool,
+    protect: &'a ProtectedPaths,
+    roots: &'a [PathBuf],
+    limits: DeletionLimits,
+    deleted_count: u64,
+    deleted_bytes: u64,
+}
+
+/// Check a file that was just written (or moved in) against the index,
+/// reporting it, and with `--delete` removing it, if it duplicates existing
+/// content.
+///
+/// Exits (with 1) once a deletion would go over a limit, too many deletions
+/// have failed, or the audit log cannot be written.
+fn check_new_file(
+    path: &Path,
+    index: &mut WatchIndex,
+    policy: &mut WatchPolicy,
+    deleter: &mut Deleter,
+) {
+    let found = match index.add(path) {
+        Ok(Some(found)) => found,
+        Ok(None) => return,
+        // Already gone again, like a temporary file.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            eprintln!(
+                "{} {}: {}",
+                "[FAILED]".red().bold(),
+                path.display(),
+                e.to_string().red()
+            );
+            return;
+        }
+    };
+
+    println!(
+        "{} {} {} {} ({})",
+        "[DUPE]".red().bold(),
+        found.path.display().to_string().cyan(),
+        "duplicates".dimmed(),
+        found.original.display().to_string().cyan(),
+        format_bytes(found.size)
+    );
+    if !policy.delete {
+        return;
+    }
+    if policy.protect.is_protected(&found.path) {
+        println!(
+            "{} {}",
+            "[PROTECTED]".blue().bold(),
+            found.path.display().to_string().cyan()
+        );
+        return;
+    }
+    if first_outside_roots([&found.path], policy.roots).is_some() {
+        eprintln!(
+            "{} {}",
+            "[REFUSED]".red().bold(),
+            format!(
+                "'{}' is outside the watched directories.",
+                found.path.display()
+            )
+            .red()
         );
         return;
     }
+    let planned_count = policy.deleted_count + 1;
+    let planned_bytes = policy.deleted_bytes + found.size;
+    if let Err(exceeded) = policy.limits.check(planned_count, planned_bytes) {
+        eprintln!(
+            "{} {}",
+            "Stopped:".red().bold(),
+            format!("{exceeded}. '{}' was not deleted.", found.path.display()).red()
+        );
+        std::process::exit(1);
+    }
+    if let Some(size) = deleter.delete_file(&found.path, &found.hash, &found.original) {
+        policy.deleted_count += 1;
+        policy.deleted_bytes += size;
+        index.remove(&found.path);
+    }
+    exit_on_audit_error(deleter);
+    exit_on_max_failures(deleter);
+}
+
+/// `ddupe watch`: index the roots, then report new files that duplicate
+/// existing content as they are written, until Ctrl-C.
+fn run_watch(args: &Args, watch: &WatchArgs, protect: &ProtectedPaths) {
+    if let Some(path) = args.path.as_ref() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "'{}' cannot be given before `watch`; list the directories after it",
+                    path.display()
+                ),
+            )
+            .exit();
+    }
+
+    // Events name absolute paths, so index the roots by their canonical form.
+    let roots: Vec<PathBuf> = watch
+        .roots
+        .iter()
+        .map(|root| match fs::canonicalize(root) {
+            Ok(root) if root.is_dir() => root,
+            _ => {
+                eprintln!(
+                    "{} {}",
+                    "Error:".red().bold(),
+                    format!("'{}' is not a directory.", root.display()).red()
+                );
+                std::process::exit(2);
+            }
+        })
+        .collect();
+
+    let cancel = CancellationToken::new();
+    install_interrupt_handler(&cancel);
+
+    // Start watching before indexing, so nothing written meanwhile is missed.
+    let (tx, rx) = std::sync::mpsc::channel();
+    let watcher = notify::recommended_watcher(tx).and_then(|mut watcher| {
+        for root in &roots {
+            watcher.watch(root, RecursiveMode::Recursive)?;
+        }
+        Ok(watcher)
+    });
+    let _watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("{} {}", "Cannot watch:".red().bold(), e.to_string().red());
+            std::process::exit(1);
+        }
+    };
+
+    let options = WalkOptions {
+        skip_hidden: args.skip_hidden,
+        ..WalkOptions::default()
+    };
+    let mut index = WatchIndex::build(&roots, &options);
+
+    let mut deleter = Deleter::new(&cancel, args.max_failures);
+    let mut policy = WatchPolicy {
+        delete: watch.delete,
+        protect,
+        roots: &roots,
+        limits: DeletionLimits {
+            max_count: args.max_delete_count,
+            max_bytes: args.max_delete_bytes,
+        },
+        deleted_count: 0,
+        deleted_bytes: 0,
+    };
+    if watch.delete
+        && let Some(path) = args.audit_log.as_ref()
+    {
+        deleter = deleter.with_audit_log(open_audit_log_or_exit(path));
+    }
 
     println!(
-        "{} {}",
-        "Scanning:".green().bold(),
-        root.display().to_string().bright_green()
+        "{} {} existing file(s) under {}. Press Ctrl-C to stop.",
+        "Watching".green().bold(),
+        index.len(),
+        roots
+            .iter()
+            .map(|root| root.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+            .bright_green()
     );
 
-    // Step 1: Collect all files under the target directory.
-    let files = collect_files(&root);
-    if files.is_empty() {
-        if !json_mode {
-            println!("{}", "No files found.".yellow());
+    // Files written to recently, by when they were last written to. Only used
+    // where files being closed are not reported.
+    let mut settling: HashMap<PathBuf, Instant> = HashMap::new();
+    while !cancel.is_cancelled() {
+        let event = match rx.recv_timeout(Duration::from_millis(200)) {
+            Ok(Ok(event)) => Some(event),
+            Ok(Err(e)) => {
+                eprintln!("{} {}", "Watch error:".yellow().bold(), e);
+                None
+            }
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        // Files are checked once closed after writing, or when moved into
+        // place; anything removed or moved away is forgotten.
+        let mut written: Vec<PathBuf> = match event {
+            None => Vec::new(),
+            Some(event) => match event.kind {
+                EventKind::Access(AccessKind::Close(AccessMode::Write))
+                | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => event.paths,
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                    if let Some(from) = event.paths.first() {
+                        index.remove(from);
+                        settling.remove(from);
+                    }
+                    event.paths.into_iter().skip(1).collect()
+                }
+                EventKind::Modify(ModifyKind::Name(RenameMode::From)) | EventKind::Remove(_) => {
+                    for path in &event.paths {
+                        index.remove(path);
+                        settling.remove(path);
+                    }
+                    Vec::new()
+                }
+                // Without close events, a file is checked once it has not
+                // been written to for a while.
+                EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any)
+                    if !REPORTS_CLOSE_WRITE =>
+                {
+                    for path in event.paths {
+                        settling.insert(path, Instant::now());
+                    }
+                    Vec::new()
+                }
+                _ => Vec::new(),
+            },
+        };
+        settling.retain(|path, last_write| {
+            let settled = last_write.elapsed() >= WATCH_SETTLE_TIME;
+            if settled {
+                written.push(path.clone());
+            }
+            !settled
+        });
+
+        for path in &written {
+            // A directory moved in brings all of its files with it.
+            let files = if path.is_dir() {
+                ddupe::collect_files_with(path, &options)
+            } else {
+                vec![path.clone()]
+            };
+            for file in files {
+                check_new_file(&file, &mut index, &mut policy, &mut deleter);
+            }
         }
-        return;
     }
+}
 
-    // Step 2: Build a hash map with a progress bar.
-    let total_files = files.len() as u64;
+fn main() {
+    // Parse command-line arguments (and configuration defaults) using clap.
+    let args = parse_args();
 
-    let multi = MultiProgress::new();
+    // Colours are for people: drop them when stdout is piped or redirected.
+    // `colored` already honours NO_COLOR and CLICOLOR_FORCE on its own.
+    if !io::stdout().is_terminal() && std::env::var_os("CLICOLOR_FORCE").is_none() {
+        colored::control::set_override(false);
+    }
 
-    let bar = multi.add(ProgressBar::new(total_files));
-    bar.set_style(
-        ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} files",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
+    if args.tui && !(io::stdin().is_terminal() && io::stdout().is_terminal()) {
+        eprintln!(
+            "{} {}",
+            "Error:".red().bold(),
+            "--tui needs an interactive terminal.".red()
+        );
+        std::process::exit(2);
+    }
 
-    let current = multi.add(ProgressBar::new_spinner());
-    current.set_style(
-        ProgressStyle::with_template("{spinner:.green} Hashing: {msg}")
-            .unwrap()
-            .tick_chars("/-\\| "),
-    );
-    current.enable_steady_tick(Duration::from_millis(100));
+    if args.output.is_some() && args.format != OutputFormat::Ndjson {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--output only applies to `--format ndjson`",
+            )
+            .exit();
+    }
+
+    let mut protect = match ProtectedPaths::new(&args.protect) {
+        Ok(protect) => protect,
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red().bold(), e.to_string().red());
+            std::process::exit(2);
+        }
+    };
 
-    // Build the map manually so we can update the bar as we go, but delegate
-    // the actual hashing logic to the library.
-    let mut map = std::collections::HashMap::new();
-    for path in &files {
-        current.set_message(path.display().to_string());
-        if let Ok(hash) = ddupe::hash_file(path) {
-            map.entry(hash).or_insert_with(Vec::new).push(path.clone());
+    match args.command.as_ref() {
+        Some(Commands::Watch(watch)) => {
+            run_watch(&args, watch, &protect);
+            return;
         }
-        bar.inc(1);
+        Some(Commands::Query(query)) => {
+            run_query(&args, query);
+            return;
+        }
+        None => {}
     }
 
-    bar.finish_with_message("Hashing complete");
-    current.finish_with_message("Hashing complete");
+    let root = args
+        .path
+        .expect("clap requires PATH when no subcommand is given");
 
-    // Step 3: Analyse duplicates using library logic.
-    let analysis = analyse_duplicates(map);
-
-    if json_mode {
-        if let Some(output_path) = args.json_output.as_ref() {
-            if let Err(e) = write_json_report(output_path, &roots, &analysis, args.interactive) {
+    // Files listed in a manifest are only ever kept.
+    let mut manifest_entries = Vec::new();
+    for path in &args.manifest {
+        match manifest::load_manifest(path) {
+            Ok(entries) => manifest_entries.extend(entries),
+            Err(e) => {
                 eprintln!(
-                    "{} {}",
-                    "Failed to write JSON report:".red().bold(),
+                    "{} {}: {}",
+                    "Error: could not read manifest".red().bold(),
+                    path.display(),
                     e.to_string().red()
                 );
-                std::process::exit(1);
+                std::process::exit(2);
             }
+        }
+    }
+    protect.add_paths(manifest_entries.iter().map(|entry| entry.path.clone()));
+    let roots = vec![root.clone()];
+    let report_mode =
+        args.json_output.is_some() || args.csv_output.is_some() || args.html_output.is_some();
+    let streaming = args.format == OutputFormat::Ndjson;
+    let porcelain = args.porcelain || args.null;
+    let fdupes = args.output_style == OutputStyle::Fdupes || args.summarize;
+    // When NDJSON, porcelain or fdupes-style records go to stdout, stdout
+    // carries nothing else.
+    let quiet_stdout = porcelain || fdupes || (streaming && args.output.is_none());
+
+    if !quiet_stdout {
+        println!(
+            "{}\nLicense: LGPL-3.0-or-later\nSource: https://github.com/Morrolan/ddupe\nDocs:   https://morrolan.github.io/ddupe\n------------------------------------------------------------",
+            "ddupe — Duplicate File Cleaner".bold()
+        );
+    }
+
+    // Basic sanity check: ensure the directory exists.
+    if !root.exists() {
+        eprintln!(
+            "{} {}",
+            "Error:".red().bold(),
+            format!("'{}' does not exist.", root.display()).red()
+        );
+        return;
+    }
+
+    if !quiet_stdout {
+        println!(
+            "{} {}",
+            "Scanning:".green().bold(),
+            root.display().to_string().bright_green()
+        );
+    }
+
+    // Step 1 & 2: Walk and hash the tree, driving the progress bars from the
+    // scanner's callbacks.
+    let cancel = CancellationToken::new();
+    install_interrupt_handler(&cancel);
+
+    let mut scanner = Scanner::new(&root)
+        .with_cancellation(cancel.clone())
+        .with_empty_files(args.empty_files.into())
+        .with_follow_symlinks(args.follow_symlinks)
+        .with_one_file_system(args.one_file_system)
+        .with_max_depth(args.max_depth)
+        .with_skip_hidden(args.skip_hidden)
+        .with_file_types(args.file_types.into())
+        // NDJSON writes each group as it is found; nothing else needs them.
+        .with_collect_groups(!streaming);
+    if let Some(checkpoint) = args.resume.as_ref() {
+        scanner = scanner.with_checkpoint(checkpoint);
+    }
+
+    let mut ndjson = if streaming {
+        let sink: Box<dyn Write> = match args.output.as_ref() {
+            Some(path) => match create_report_file(path) {
+                Ok(file) => Box::new(io::BufWriter::new(file)),
+                Err(e) => {
+                    eprintln!(
+                        "{} {}",
+                        "Failed to create NDJSON output:".red().bold(),
+                        e.to_string().red()
+                    );
+                    std::process::exit(1);
+                }
+            },
+            None => Box::new(io::stdout()),
+        };
+        Some(NdjsonObserver {
+            writer: NdjsonWriter::new(sink),
+            protect: protect.clone(),
+            error: None,
+        })
+    } else {
+        None
+    };
+
+    let started_at = SystemTime::now();
+    let mut observer = ProgressObserver::new(!porcelain);
+    let mut index = DirectoryIndex::new();
+    let mut recorder = args.index.as_ref().map(|_| IndexRecorder::new());
+    let mut hashes =
+        (!args.manifest.is_empty() || args.manifest_output.is_some()).then(HashRecorder::new);
+    let (mut no_index, mut no_hashes) = (NoopObserver, NoopObserver);
+    let indexing: &mut dyn ScanObserver = match recorder.as_mut() {
+        Some(recorder) => recorder,
+        None => &mut no_index,
+    };
+    let hashing: &mut dyn ScanObserver = match hashes.as_mut() {
+        Some(hashes) => hashes,
+        None => &mut no_hashes,
+    };
+    let mut recording = (indexing, hashing);
+    let outcome = match ndjson.as_mut() {
+        Some(ndjson) => scanner.scan(&mut (&mut observer, (ndjson, &mut recording))),
+        None if args.dirs => scanner.scan(&mut (&mut observer, (&mut index, &mut recording))),
+        None => scanner.scan(&mut (&mut observer, &mut recording)),
+    };
+    observer.finish();
+    let finished_at = SystemTime::now();
+
+    if outcome.cancelled {
+        eprintln!(
+            "{}",
+            "Scan interrupted: showing partial results for the files hashed so far."
+                .yellow()
+                .bold()
+        );
+    }
+
+    if let (Some(path), Some(recorder)) = (args.index.as_ref(), recorder) {
+        if outcome.cancelled {
+            eprintln!(
+                "{}",
+                "Index not updated: the scan was interrupted.".yellow()
+            );
+        } else {
+            update_index(path, &roots, recorder, quiet_stdout);
+        }
+    }
+
+    let scanned = hashes.map(HashRecorder::into_entries).unwrap_or_default();
+    if let Some(path) = args.manifest_output.as_ref() {
+        if outcome.cancelled {
+            eprintln!(
+                "{}",
+                "Manifest not written: the scan was interrupted.".yellow()
+            );
+        } else {
+            write_manifest_or_exit(path, args.manifest_format.into(), &scanned, quiet_stdout);
+        }
+    }
+
+    if let Some(ndjson) = ndjson {
+        let context = ReportContext {
+            roots: roots.clone(),
+            mode: "ndjson".to_string(),
+            dry_run: true,
+            interactive: args.interactive,
+            started_at,
+            finished_at,
+        };
+        let result = match ndjson.error {
+            Some(e) => Err(e),
+            None => ndjson.writer.finish(&context, outcome.cancelled).map(drop),
+        };
+        if let Err(e) = result {
+            eprintln!(
+                "{} {}",
+                "Failed to write NDJSON output:".red().bold(),
+                e.to_string().red()
+            );
+            std::process::exit(1);
+        }
+        if let Some(path) = args.output.as_ref() {
             println!(
                 "{} {}",
-                "JSON report written to:".blue().bold(),
-                output_path.display().to_string().cyan()
+                "NDJSON written to:".blue().bold(),
+                path.display().to_string().cyan()
+            );
+        }
+        if outcome.cancelled {
+            std::process::exit(130);
+        }
+        return;
+    }
+
+    // Machine-readable listings handle an empty scan themselves.
+    if outcome.files_scanned == 0 && !porcelain && !fdupes {
+        if outcome.cancelled {
+            eprintln!("{}", "Interrupted before any file was found.".yellow());
+            std::process::exit(130);
+        }
+        if !report_mode {
+            println!("{}", "No files found.".yellow());
+        }
+        return;
+    }
+
+    // Step 3: Duplicate analysis is produced by the scanner using library logic.
+    let mut analysis = manifest::merge_manifest(outcome.analysis, &scanned, &manifest_entries);
+    let mut directory_groups = if args.dirs {
+        index.duplicate_directories()
+    } else {
+        Vec::new()
+    };
+    // Redundant directories holding anything protected are never removed.
+    for group in &mut directory_groups {
+        group.dupes.retain(|dir| !protect.protects_within(dir));
+    }
+    directory_groups.retain(|group| !group.dupes.is_empty());
+    if !directory_groups.is_empty() {
+        analysis = directories::without_covered_files(&analysis, &directory_groups);
+    }
+    let mut analysis = protect.apply(analysis);
+    // Statistics describe the whole scan, not just the `--top` groups.
+    let stats = args.stats.then(|| {
+        let deletable = if args.trust_manifest {
+            analysis.clone()
+        } else {
+            manifest::split_manifest_groups(analysis.clone(), &manifest_entries).0
+        };
+        WasteStats::from_analysis(&deletable, &roots)
+    });
+    analysis.sort_groups(args.sort.into());
+    if let Some(top) = args.top {
+        analysis.truncate(top);
+    }
+
+    if porcelain || fdupes {
+        let stdout = io::stdout().lock();
+        let result = if args.summarize {
+            ddupe::report::write_fdupes_summary(stdout, &analysis)
+        } else if fdupes {
+            ddupe::report::write_fdupes(stdout, &analysis)
+        } else {
+            let terminator = if args.null { b'\0' } else { b'\n' };
+            ddupe::report::write_porcelain(stdout, &analysis, terminator)
+        };
+        if let Err(e) = result {
+            eprintln!(
+                "{} {}",
+                "Failed to write output:".red().bold(),
+                e.to_string().red()
             );
+            std::process::exit(1);
+        }
+        if outcome.cancelled {
+            std::process::exit(130);
+        }
+        return;
+    }
+
+    if report_mode {
+        let context = ReportContext {
+            roots: roots.clone(),
+            mode: if args.json_output.is_some() {
+                "json"
+            } else if args.csv_output.is_some() {
+                "csv"
+            } else {
+                "html"
+            }
+            .to_string(),
+            // Report modes never delete anything.
+            dry_run: true,
+            interactive: args.interactive,
+            started_at,
+            finished_at,
+        };
+        if let Some(output_path) = args.json_output.as_ref() {
+            write_report_or_exit("JSON", output_path, || {
+                write_json_report(output_path, &analysis, &context)
+            });
+        }
+        if let Some(output_path) = args.csv_output.as_ref() {
+            write_report_or_exit("CSV", output_path, || {
+                write_csv_report(output_path, &analysis)
+            });
+        }
+        if let Some(output_path) = args.html_output.as_ref() {
+            write_report_or_exit("HTML", output_path, || {
+                write_html_report(output_path, &analysis, &context)
+            });
+        }
+        if outcome.cancelled {
+            std::process::exit(130);
         }
         return;
     }
 
+    // A copy listed only in a manifest cannot be checked from here, so the
+    // scanned copies of it are listed but left alone without --trust-manifest.
+    let (analysis, manifest_groups) = if args.trust_manifest {
+        (analysis, ddupe::DuplicateAnalysis::default())
+    } else {
+        manifest::split_manifest_groups(analysis, &manifest_entries)
+    };
+
+    if !outcome.empty_files.is_empty() {
+        println!(
+            "\n{} {}",
+            "Empty files:".yellow().bold(),
+            "(not treated as duplicates)".yellow()
+        );
+        for path in &outcome.empty_files {
+            println!(
+                "{} {}",
+                "[EMPTY]".yellow().bold(),
+                path.display().to_string().cyan()
+            );
+        }
+    }
+
+    if !outcome.linked_copies.is_empty() {
+        println!(
+            "\n{} {}",
+            "Symlinked copies:".yellow().bold(),
+            "(symlinks are never deleted)".yellow()
+        );
+        for (link, original) in &outcome.linked_copies {
+            println!(
+                "{} {} {} {}",
+                "[LINK]".yellow().bold(),
+                link.display().to_string().cyan(),
+                "same content as".dimmed(),
+                original.display().to_string().green()
+            );
+        }
+    }
+
+    if !directory_groups.is_empty() {
+        print_directory_groups(&directory_groups);
+    }
+
     println!("\n{}", "Duplicate files found:".yellow().bold());
 
-    if analysis.groups.is_empty() {
+    if analysis.groups.is_empty()
+        && manifest_groups.groups.is_empty()
+        && directory_groups.is_empty()
+    {
+        // Files not hashed before the interruption may still have copies.
+        if outcome.cancelled {
+            println!(
+                "{} {}",
+                "Interrupted:".yellow().bold(),
+                "no duplicates found among the files hashed so far.".yellow()
+            );
+            std::process::exit(130);
+        }
         println!("{}", "No duplicates found 🎉".bright_green().bold());
         return;
     }
 
-    // Print groups with KEEP/DUPE markers.
-    for (idx, group) in analysis.groups.iter().enumerate() {
+    let mut mounts = args.show_mounts.then(MountResolver::new);
+
+    // Print groups with KEEP/DUPE markers; copies of manifest files are only
+    // LISTED, since they are not deleted.
+    let groups = analysis.groups.iter().chain(&manifest_groups.groups);
+    for (idx, group) in groups.enumerate() {
+        let listed = idx >= analysis.groups.len();
         println!(
             "\n{} {} {}",
             "---".bright_yellow(),
@@ -418,17 +2247,27 @@ fn main() {
         );
 
         println!(
-            "{} {}",
+            "{} {}{}",
             "[KEEP]".green().bold(),
-            group.keep.display().to_string().cyan()
+            group.keep.display().to_string().cyan(),
+            if listed {
+                " (manifest)".dimmed()
+            } else {
+                "".normal()
+            }
         );
 
         for dupe in &group.dupes {
-            println!(
-                "{} {}",
-                "[DUPE]".red().bold(),
-                dupe.display().to_string().cyan()
-            );
+            let marker = if listed {
+                "[LISTED]".yellow().bold()
+            } else {
+                "[DUPE]".red().bold()
+            };
+            println!("{} {}", marker, dupe.display().to_string().cyan());
+        }
+
+        if let Some(resolver) = mounts.as_mut() {
+            print_group_mounts(&resolver.group_mounts(group));
         }
     }
 
@@ -441,8 +2280,51 @@ fn main() {
             .bold()
     );
 
-    // If there are no files to remove (shouldn't happen if groups non-empty), we're done.
-    if analysis.removable_files.is_empty() {
+    if !manifest_groups.groups.is_empty() {
+        println!(
+            "{} {} file(s) have a copy listed in a manifest; pass --trust-manifest to delete them too.",
+            "Summary:".blue().bold(),
+            manifest_groups.total_dupes().to_string().bright_yellow()
+        );
+    }
+
+    if !directory_groups.is_empty() {
+        println!(
+            "{} {} redundant directory(ies) can be removed, freeing approximately {}.",
+            "Summary:".blue().bold(),
+            directory_groups
+                .iter()
+                .map(|g| g.dupes.len())
+                .sum::<usize>()
+                .to_string()
+                .bright_yellow(),
+            format_bytes(
+                directory_groups
+                    .iter()
+                    .map(DirectoryGroup::reclaimable_bytes)
+                    .sum()
+            )
+            .bright_green()
+            .bold()
+        );
+    }
+
+    if let Some(stats) = &stats {
+        print_stats(stats);
+    }
+
+    // A partial scan is only good for reporting; never delete based on it.
+    if outcome.cancelled {
+        println!(
+            "\n{} {}",
+            "Interrupted:".yellow().bold(),
+            "no files were deleted.".yellow()
+        );
+        std::process::exit(130);
+    }
+
+    // If there is nothing to remove (shouldn't happen if groups non-empty), we're done.
+    if analysis.removable_files.is_empty() && directory_groups.is_empty() {
         return;
     }
 
@@ -456,16 +2338,94 @@ fn main() {
         return;
     }
 
-    // Interactive deletion flow: decide per duplicate.
-    if args.interactive {
-        let (deleted_count, deleted_bytes) = delete_files_interactively(&analysis.groups);
+    // Last line of defence: the plan must not touch anything protected.
+    let plan = analysis
+        .removable_files
+        .iter()
+        .chain(directory_groups.iter().flat_map(|g| &g.dupes));
+    if let Some(path) = protect.first_violation(plan) {
+        eprintln!(
+            "{} {}",
+            "Refusing to run:".red().bold(),
+            format!("the plan would touch protected path '{}'.", path.display()).red()
+        );
+        std::process::exit(1);
+    }
 
-        println!(
-            "\n{} Deleted {} file(s), freeing approximately {}.",
-            "Done:".green().bold(),
-            deleted_count.to_string().bright_yellow(),
-            format_bytes(deleted_bytes).bright_green().bold()
+    // Circuit breakers: refuse plans that reach outside the scanned roots or
+    // delete more than the configured limits.
+    let plan = analysis
+        .removable_files
+        .iter()
+        .chain(directory_groups.iter().flat_map(|g| &g.dupes));
+    if let Some(path) = first_outside_roots(plan, &roots) {
+        eprintln!(
+            "{} {}",
+            "Refusing to run:".red().bold(),
+            format!("'{}' is outside the scanned directory.", path.display()).red()
+        );
+        std::process::exit(1);
+    }
+    let limits = DeletionLimits {
+        max_count: args.max_delete_count,
+        max_bytes: args.max_delete_bytes,
+    };
+    // Redundant directories only lose the files the scan compared.
+    let planned_count = analysis.removable_files.len() as u64
+        + directory_groups
+            .iter()
+            .map(|g| (g.contents.len() * g.dupes.len()) as u64)
+            .sum::<u64>();
+    let planned_bytes = analysis.total_saving_bytes
+        + directory_groups
+            .iter()
+            .map(DirectoryGroup::reclaimable_bytes)
+            .sum::<u64>();
+    if let Err(exceeded) = limits.check(planned_count, planned_bytes) {
+        eprintln!(
+            "{} {}",
+            "Refusing to run:".red().bold(),
+            format!("{exceeded}. Nothing was deleted.").red()
         );
+        std::process::exit(1);
+    }
+
+    let mut deleter = Deleter::new(&cancel, args.max_failures);
+    if let Some(path) = args.audit_log.as_ref() {
+        deleter = deleter.with_audit_log(open_audit_log_or_exit(path));
+    }
+
+    // Interactive deletion flow: decide per duplicate.
+    let (deleted_count, deleted_bytes) = if args.tui {
+        let plan = match tui::review(&analysis.groups, &protect) {
+            Ok(Some(plan)) => plan,
+            Ok(None) => {
+                println!("{}", "Aborted. No files were deleted.".yellow());
+                return;
+            }
+            Err(e) => {
+                eprintln!(
+                    "{} {}",
+                    "Terminal UI failed:".red().bold(),
+                    e.to_string().red()
+                );
+                std::process::exit(1);
+            }
+        };
+        // The review can only narrow the plan, but check it once more anyway.
+        if let Some(path) = protect.first_violation(plan.iter().flat_map(|g| &g.dupes)) {
+            eprintln!(
+                "{} {}",
+                "Refusing to run:".red().bold(),
+                format!("the plan would touch protected path '{}'.", path.display()).red()
+            );
+            std::process::exit(1);
+        }
+        delete_files(&plan, &mut deleter)
+    } else if args.interactive {
+        let (dir_count, dir_bytes) = delete_directories(&directory_groups, &mut deleter, true);
+        let (count, bytes) = delete_files_interactively(&analysis.groups, &protect, &mut deleter);
+        (dir_count + count, dir_bytes + bytes)
     } else {
         // Ask the user if they actually want to delete the duplicates.
         if !ask_user_to_confirm() {
@@ -473,15 +2433,30 @@ fn main() {
             return;
         }
 
-        // Delete the duplicates and report the result.
-        let (deleted_count, deleted_bytes) = delete_files(&analysis.removable_files);
+        // Delete redundant directories first, then the remaining duplicates.
+        let (dir_count, dir_bytes) = delete_directories(&directory_groups, &mut deleter, false);
+        let (count, bytes) = delete_files(&analysis.groups, &mut deleter);
+        (dir_count + count, dir_bytes + bytes)
+    };
 
-        println!(
-            "\n{} Deleted {} file(s), freeing approximately {}.",
-            "Done:".green().bold(),
-            deleted_count.to_string().bright_yellow(),
-            format_bytes(deleted_bytes).bright_green().bold()
-        );
+    print_deletion_summary(deleted_count, deleted_bytes, &cancel);
+    exit_on_audit_error(&deleter);
+    exit_on_max_failures(&deleter);
+    if cancel.is_cancelled() {
+        std::process::exit(130);
+    }
+
+    if args.prune_empty_dirs {
+        // Anything that might have been deleted: interactive mode can keep a
+        // different file than the default, so consider every group member.
+        let touched: Vec<PathBuf> = analysis
+            .groups
+            .iter()
+            .flat_map(|g| std::iter::once(&g.keep).chain(&g.dupes))
+            .chain(directory_groups.iter().flat_map(|g| &g.dupes))
+            .cloned()
+            .collect();
+        print_pruned_dirs(&ddupe::prune_empty_dirs(&touched, &roots));
     }
 }
 
@@ -499,17 +2474,116 @@ mod tests {
         path
     }
 
+    fn group_of(dupes: &[PathBuf]) -> Vec<DuplicateGroup> {
+        vec![DuplicateGroup {
+            hash: "hash".to_string(),
+            size: 0,
+            keep: PathBuf::from("keep"),
+            dupes: dupes.to_vec(),
+        }]
+    }
+
+    #[test]
+    fn parse_choice_understands_every_command() {
+        assert_eq!(parse_choice("2\n", 3), Some(Choice::Keep(1)));
+        assert_eq!(parse_choice("4", 3), None);
+        assert_eq!(parse_choice("0", 3), None);
+        assert_eq!(parse_choice("", 3), Some(Choice::KeepAll));
+        assert_eq!(parse_choice("A", 3), Some(Choice::KeepAll));
+        assert_eq!(parse_choice("s", 3), Some(Choice::Skip));
+        assert_eq!(parse_choice("b", 3), Some(Choice::Back));
+        assert_eq!(parse_choice("Q", 3), Some(Choice::Quit));
+        assert_eq!(
+            parse_choice("r /archive \n", 3),
+            Some(Choice::Rule(PathBuf::from("/archive")))
+        );
+        assert_eq!(parse_choice("x", 3), None);
+    }
+
+    #[test]
+    fn decide_groups_supports_back_rules_and_quit() {
+        let groups: Vec<DuplicateGroup> = ["/archive/a", "/archive/b", "/other/c", "/archive/d"]
+            .iter()
+            .map(|copy| {
+                let copy = PathBuf::from(copy);
+                DuplicateGroup {
+                    hash: "hash".to_string(),
+                    size: 1,
+                    keep: Path::new("/inbox").join(copy.file_name().unwrap()),
+                    dupes: vec![copy],
+                }
+            })
+            .collect();
+
+        let mut script = vec![
+            Choice::Skip,
+            Choice::Back,
+            Choice::Keep(1),
+            Choice::Rule(PathBuf::from("/archive")),
+            Choice::Quit,
+        ]
+        .into_iter();
+        let mut asked = Vec::new();
+        let decisions = decide_groups(&groups, |idx, _| {
+            asked.push(idx);
+            script.next().unwrap()
+        });
+
+        // Group 1 is decided by the rule once recorded; group 2 has no copy
+        // under /archive and is asked about, then quitting leaves group 3 alone.
+        assert_eq!(asked, [0, 1, 0, 1, 2]);
+        assert_eq!(decisions, [Some(1), Some(1), None, None]);
+    }
+
     #[test]
     fn delete_files_removes_and_counts_bytes() {
         let dir = TempDir::new().unwrap();
         let one = write_file(&dir, "one.txt", b"abc"); // 3 bytes
         let two = write_file(&dir, "two.txt", b"1234"); // 4 bytes
 
-        let (count, bytes) = delete_files(&[one.clone(), two.clone()]);
+        let cancel = CancellationToken::new();
+        let (count, bytes) = delete_files(
+            &group_of(&[one.clone(), two.clone()]),
+            &mut Deleter::new(&cancel, None),
+        );
 
         assert_eq!(count, 2);
         assert_eq!(bytes, 7);
         assert!(!one.exists());
         assert!(!two.exists());
     }
+
+    #[test]
+    fn delete_files_stops_after_max_failures() {
+        let dir = TempDir::new().unwrap();
+        // Directories cannot be removed with `remove_file`, so both fail.
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        fs::create_dir(&first).unwrap();
+        fs::create_dir(&second).unwrap();
+
+        let cancel = CancellationToken::new();
+        let mut deleter = Deleter::new(&cancel, Some(1));
+        let (count, _) = delete_files(&group_of(&[first, second]), &mut deleter);
+
+        assert_eq!(count, 0);
+        assert!(deleter.failures.tripped());
+        assert_eq!(deleter.failures.failures(), 1);
+    }
+
+    #[test]
+    fn delete_files_stops_when_cancelled() {
+        let dir = TempDir::new().unwrap();
+        let one = write_file(&dir, "one.txt", b"abc");
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let (count, bytes) = delete_files(
+            &group_of(std::slice::from_ref(&one)),
+            &mut Deleter::new(&cancel, None),
+        );
+
+        assert_eq!((count, bytes), (0, 0));
+        assert!(one.exists());
+    }
 }
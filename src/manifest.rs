@@ -0,0 +1,497 @@
+//! `sha256sum` and hashdeep manifests.
+//!
+//! A manifest lists files with their SHA-256 (and, in hashdeep's format, their
+//! size). ddupe can write one for every file a scan hashed, and read one so
+//! that files it cannot reach, such as an offline or remote archive, take part
+//! in duplicate detection: see [`merge_manifest`].
+
+use crate::{DuplicateAnalysis, DuplicateGroup, GroupOrder, ScanObserver, scan::EMPTY_SHA256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+/// First line of a hashdeep manifest.
+const HASHDEEP_HEADER: &str = "%%%% HASHDEEP-1.0";
+
+/// Manifest file formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ManifestFormat {
+    /// `sha256sum` output: `<hash>  <path>` per line.
+    #[default]
+    Sha256sum,
+    /// hashdeep (md5deep family) output: a header, then `size,sha256,path`.
+    Hashdeep,
+}
+
+/// One file listed in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path as written in the manifest.
+    pub path: PathBuf,
+    /// Size in bytes, if the manifest records it.
+    pub size: Option<u64>,
+    /// Lower-case hex SHA-256 of the contents.
+    pub hash: String,
+}
+
+/// Write `entries` to `writer` in `format`.
+///
+/// Paths containing a line break or backslash are escaped the way GNU
+/// `sha256sum` does it, with a leading `\` on the line. hashdeep has no
+/// escapes, so a path with a line break is an `InvalidInput` error there.
+pub fn write_manifest(
+    mut writer: impl Write,
+    format: ManifestFormat,
+    entries: &[ManifestEntry],
+) -> io::Result<()> {
+    match format {
+        ManifestFormat::Sha256sum => {
+            for entry in entries {
+                let path = entry.path.to_string_lossy();
+                if path.contains(['\n', '\r', '\\']) {
+                    let escaped = path
+                        .replace('\\', "\\\\")
+                        .replace('\n', "\\n")
+                        .replace('\r', "\\r");
+                    writeln!(writer, "\\{}  {}", entry.hash, escaped)?;
+                } else {
+                    writeln!(writer, "{}  {}", entry.hash, path)?;
+                }
+            }
+        }
+        ManifestFormat::Hashdeep => {
+            writeln!(writer, "{HASHDEEP_HEADER}")?;
+            writeln!(writer, "%%%% size,sha256,filename")?;
+            writeln!(writer, "## Written by ddupe")?;
+            writeln!(writer, "##")?;
+            for entry in entries {
+                let path = entry.path.to_string_lossy();
+                if path.contains(['\n', '\r']) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("hashdeep manifests cannot hold the path {path:?}"),
+                    ));
+                }
+                writeln!(
+                    writer,
+                    "{},{},{}",
+                    entry.size.unwrap_or_default(),
+                    entry.hash,
+                    path
+                )?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Read a manifest, telling the format from its first line.
+///
+/// hashdeep manifests must have a `sha256` column; other columns (`md5`, ...)
+/// are ignored. Malformed lines are `InvalidData` errors naming the line.
+pub fn read_manifest(reader: impl BufRead) -> io::Result<Vec<ManifestEntry>> {
+    let mut lines = reader.lines().enumerate().peekable();
+    let hashdeep =
+        matches!(lines.peek(), Some((_, Ok(line))) if line.trim_end() == HASHDEEP_HEADER);
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut entries = Vec::new();
+    for (number, line) in lines {
+        let line = line?;
+        let invalid = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {what}", number + 1),
+            )
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if !hashdeep {
+            entries
+                .push(parse_sha256sum_line(&line).ok_or_else(|| invalid("not a sha256sum line"))?);
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("%%%% ") {
+            if header != &HASHDEEP_HEADER[5..] {
+                columns = header.split(',').map(str::to_string).collect();
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let sha256 = columns
+            .iter()
+            .position(|c| c == "sha256")
+            .ok_or_else(|| invalid("the hashdeep header has no sha256 column"))?;
+        let fields: Vec<&str> = line.splitn(columns.len(), ',').collect();
+        if fields.len() != columns.len() || columns.last().map(String::as_str) != Some("filename") {
+            return Err(invalid("does not match the hashdeep header"));
+        }
+        let hash = fields[sha256].to_ascii_lowercase();
+        if !is_sha256(&hash) {
+            return Err(invalid("not a SHA-256 hash"));
+        }
+        let size = match columns.iter().position(|c| c == "size") {
+            Some(i) => Some(fields[i].parse().map_err(|_| invalid("bad size"))?),
+            None => None,
+        };
+        entries.push(ManifestEntry {
+            path: PathBuf::from(fields[columns.len() - 1]),
+            size,
+            hash,
+        });
+    }
+    Ok(entries)
+}
+
+/// Read the manifest file at `path`.
+///
+/// Relative paths in the file are resolved against the directory holding
+/// it, which is where `cd dir && sha256sum * > dir.sha256` leaves them.
+pub fn load_manifest(path: &Path) -> io::Result<Vec<ManifestEntry>> {
+    let mut entries = read_manifest(BufReader::new(File::open(path)?))
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let base = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => std::path::absolute(parent)?,
+        _ => std::env::current_dir()?,
+    };
+    for entry in &mut entries {
+        if entry.path.is_relative() {
+            entry.path = base.join(&entry.path);
+        }
+    }
+    Ok(entries)
+}
+
+/// Parse `<hash>  <path>` (text mode) or `<hash> *<path>` (binary mode).
+fn parse_sha256sum_line(line: &str) -> Option<ManifestEntry> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (hash, rest) = line.split_at_checked(64)?;
+    let path = rest
+        .strip_prefix("  ")
+        .or_else(|| rest.strip_prefix(" *"))?;
+    if !is_sha256(hash) || path.is_empty() {
+        return None;
+    }
+    let path = if escaped {
+        unescape(path)?
+    } else {
+        path.to_string()
+    };
+    Some(ManifestEntry {
+        path: PathBuf::from(path),
+        size: None,
+        hash: hash.to_ascii_lowercase(),
+    })
+}
+
+/// Undo `sha256sum`'s `\\`, `\n` and `\r` escapes.
+fn unescape(path: &str) -> Option<String> {
+    let mut out = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => out.push('\\'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+fn is_sha256(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Collects every file a scan hashes, with its size, for writing a manifest
+/// or for [`merge_manifest`].
+#[derive(Debug, Default)]
+pub struct HashRecorder {
+    entries: Vec<ManifestEntry>,
+}
+
+impl HashRecorder {
+    /// Create an empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The recorded files, sorted by path.
+    pub fn into_entries(mut self) -> Vec<ManifestEntry> {
+        self.entries.sort_by(|a, b| a.path.cmp(&b.path));
+        self.entries
+    }
+}
+
+impl ScanObserver for HashRecorder {
+    fn hashing_finished(&mut self, path: &Path, hash: &str) {
+        self.entries.push(ManifestEntry {
+            path: path.to_path_buf(),
+            size: fs::metadata(path).ok().map(|meta| meta.len()),
+            hash: hash.to_string(),
+        });
+    }
+}
+
+/// Add the files of a manifest to an analysis of scanned files.
+///
+/// `scanned` lists every file the scan hashed (see [`HashRecorder`]). When a
+/// manifest lists content that was also scanned, its first copy (by path)
+/// becomes the keep of a group whose dupes are the scanned copies not already
+/// offered for deletion: the keep of a local group, or a file that had no
+/// local duplicate. Local groups are left as they are, so their duplicates
+/// stay deletable whether or not the manifest is trusted (see
+/// [`split_manifest_groups`]). Manifest files are never dupes: they cannot
+/// be deleted from here, so callers should also protect them. Empty files,
+/// symlinks and manifest entries that are one of the scanned files, however
+/// the path is spelled, are ignored.
+pub fn merge_manifest(
+    analysis: DuplicateAnalysis,
+    scanned: &[ManifestEntry],
+    manifest: &[ManifestEntry],
+) -> DuplicateAnalysis {
+    let scanned_files = scanned_files(scanned, manifest);
+    let mut remote: BTreeMap<&str, &Path> = BTreeMap::new();
+    for entry in manifest {
+        let is_scanned = fs::canonicalize(&entry.path).is_ok_and(|p| scanned_files.contains(&p));
+        if entry.hash == EMPTY_SHA256 || is_scanned {
+            continue;
+        }
+        let keep = remote.entry(entry.hash.as_str()).or_insert(&entry.path);
+        if entry.path.as_path() < *keep {
+            *keep = &entry.path;
+        }
+    }
+    if remote.is_empty() {
+        return analysis;
+    }
+
+    let grouped: HashSet<String> = analysis.groups.iter().map(|g| g.hash.clone()).collect();
+    // The local keep of content the manifest also lists.
+    let listed: Vec<DuplicateGroup> = analysis
+        .groups
+        .iter()
+        .filter_map(|group| {
+            let keep = remote.get(group.hash.as_str())?;
+            Some(DuplicateGroup {
+                keep: keep.to_path_buf(),
+                dupes: vec![group.keep.clone()],
+                ..group.clone()
+            })
+        })
+        .collect();
+    let mut groups = analysis.groups;
+    groups.extend(listed);
+
+    // Scanned files with no local duplicate, but a copy in the manifest.
+    let mut singles: HashMap<&str, DuplicateGroup> = HashMap::new();
+    for entry in scanned {
+        let Some(keep) = remote.get(entry.hash.as_str()) else {
+            continue;
+        };
+        let is_symlink =
+            fs::symlink_metadata(&entry.path).is_ok_and(|m| m.file_type().is_symlink());
+        if grouped.contains(&entry.hash) || is_symlink || entry.size == Some(0) {
+            continue;
+        }
+        singles
+            .entry(entry.hash.as_str())
+            .or_insert_with(|| DuplicateGroup {
+                hash: entry.hash.clone(),
+                size: entry.size.unwrap_or_default(),
+                keep: keep.to_path_buf(),
+                dupes: Vec::new(),
+            })
+            .dupes
+            .push(entry.path.clone());
+    }
+    groups.extend(singles.into_values());
+
+    let mut merged = DuplicateAnalysis {
+        removable_files: Vec::new(),
+        total_saving_bytes: groups.iter().map(|g| g.reclaimable_bytes()).sum(),
+        groups,
+    };
+    merged.sort_groups(GroupOrder::Path);
+    merged
+}
+
+/// Canonical paths of the `scanned` files, or nothing when no `manifest`
+/// entry exists locally and so none can be one of them.
+fn scanned_files(scanned: &[ManifestEntry], manifest: &[ManifestEntry]) -> HashSet<PathBuf> {
+    if !manifest.iter().any(|entry| entry.path.exists()) {
+        return HashSet::new();
+    }
+    scanned
+        .iter()
+        .filter_map(|entry| fs::canonicalize(&entry.path).ok())
+        .collect()
+}
+
+/// Split an analysis from [`merge_manifest`] into the groups that keep a
+/// scanned file and the groups that keep a `manifest` file.
+///
+/// A manifest copy cannot be checked from here, so callers may want to
+/// report the second set without deleting anything in it.
+pub fn split_manifest_groups(
+    analysis: DuplicateAnalysis,
+    manifest: &[ManifestEntry],
+) -> (DuplicateAnalysis, DuplicateAnalysis) {
+    let listed: HashSet<&Path> = manifest.iter().map(|e| e.path.as_path()).collect();
+    let (remote, local): (Vec<DuplicateGroup>, Vec<DuplicateGroup>) = analysis
+        .groups
+        .into_iter()
+        .partition(|group| listed.contains(group.keep.as_path()));
+    let totals = |groups: Vec<DuplicateGroup>| DuplicateAnalysis {
+        removable_files: groups
+            .iter()
+            .flat_map(|g| g.dupes.iter().cloned())
+            .collect(),
+        total_saving_bytes: groups.iter().map(|g| g.reclaimable_bytes()).sum(),
+        groups,
+    };
+    (totals(local), totals(remote))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scanner;
+    use tempfile::TempDir;
+
+    fn entry(path: &str, hash: char) -> ManifestEntry {
+        ManifestEntry {
+            path: path.into(),
+            size: Some(4),
+            hash: hash.to_string().repeat(64),
+        }
+    }
+
+    #[test]
+    fn manifests_round_trip_in_both_formats() {
+        let entries = [entry("photos/a.jpg", 'a'), entry("odd\\name\n\r.txt", 'b')];
+        for format in [ManifestFormat::Sha256sum, ManifestFormat::Hashdeep] {
+            let mut out = Vec::new();
+            write_manifest(&mut out, format, &entries[..1]).unwrap();
+            let read = read_manifest(out.as_slice()).unwrap();
+            let expected_size = (format == ManifestFormat::Hashdeep).then_some(4);
+            assert_eq!(read.len(), 1);
+            assert_eq!(read[0].path, entries[0].path);
+            assert_eq!(read[0].hash, entries[0].hash);
+            assert_eq!(read[0].size, expected_size);
+        }
+
+        let mut out = Vec::new();
+        write_manifest(&mut out, ManifestFormat::Sha256sum, &entries[1..]).unwrap();
+        assert!(out.starts_with(b"\\"));
+        assert_eq!(
+            read_manifest(out.as_slice()).unwrap()[0].path,
+            entries[1].path
+        );
+
+        let err = write_manifest(Vec::new(), ManifestFormat::Hashdeep, &entries[1..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn hashdeep_manifests_with_extra_columns_are_read() {
+        let text = format!(
+            "%%%% HASHDEEP-1.0\n%%%% size,md5,sha256,filename\n## Invoked from: /\n##\n5,{},{},/a,b.txt\n",
+            "0".repeat(32),
+            "C".repeat(64)
+        );
+        let entries = read_manifest(text.as_bytes()).unwrap();
+        assert_eq!(entries[0].path, PathBuf::from("/a,b.txt"));
+        assert_eq!(entries[0].size, Some(5));
+        assert_eq!(entries[0].hash, "c".repeat(64));
+
+        let err = read_manifest("not a manifest\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 1"));
+    }
+
+    #[test]
+    fn manifest_copies_are_kept_and_scanned_copies_become_dupes() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.jpg"), b"photo").unwrap();
+        fs::write(dir.path().join("b.jpg"), b"photo").unwrap();
+        fs::write(dir.path().join("c.jpg"), b"album").unwrap();
+        fs::write(dir.path().join("d.jpg"), b"local").unwrap();
+
+        let mut recorder = HashRecorder::new();
+        let outcome = Scanner::new(dir.path()).scan(&mut recorder);
+        let scanned = recorder.into_entries();
+        let hash_of = |name: &str| {
+            scanned
+                .iter()
+                .find(|e| e.path.ends_with(name))
+                .unwrap()
+                .hash
+                .clone()
+        };
+        let remote = |path: &str, name: &str| ManifestEntry {
+            path: path.into(),
+            size: Some(5),
+            hash: hash_of(name),
+        };
+        let manifest = [
+            remote("/offline/z.jpg", "a.jpg"),
+            remote("/offline/y.jpg", "a.jpg"),
+            remote("/offline/c.jpg", "c.jpg"),
+        ];
+
+        let merged = merge_manifest(outcome.analysis, &scanned, &manifest);
+        assert_eq!(merged.groups.len(), 3);
+        let album = &merged.groups[0];
+        assert_eq!(album.keep, PathBuf::from("/offline/c.jpg"));
+        assert_eq!(album.dupes, [dir.path().join("c.jpg")]);
+        // The local group keeps a local copy; the manifest copy only stands
+        // in for that one.
+        let listed = &merged.groups[1];
+        assert_eq!(listed.keep, PathBuf::from("/offline/y.jpg"));
+        assert_eq!(listed.dupes, [dir.path().join("a.jpg")]);
+        let photos = &merged.groups[2];
+        assert_eq!(photos.keep, dir.path().join("a.jpg"));
+        assert_eq!(photos.dupes, [dir.path().join("b.jpg")]);
+        assert_eq!(merged.removable_files.len(), 3);
+        assert_eq!(merged.total_saving_bytes, 15);
+
+        let (local, remote) = split_manifest_groups(merged, &manifest);
+        assert_eq!(local.removable_files, [dir.path().join("b.jpg")]);
+        assert_eq!(remote.removable_files.len(), 2);
+    }
+
+    #[test]
+    fn manifest_entries_for_the_scanned_files_themselves_are_ignored() {
+        let dir = TempDir::new().unwrap();
+        let photos = dir.path().join("photos");
+        fs::create_dir(&photos).unwrap();
+        fs::write(photos.join("a.jpg"), b"photo").unwrap();
+        let hash = crate::hash_file(&photos.join("a.jpg")).unwrap();
+        // As written by `cd photos && sha256sum a.jpg > m.txt`.
+        fs::write(photos.join("m.txt"), format!("{hash}  a.jpg\n")).unwrap();
+
+        let manifest = load_manifest(&photos.join("m.txt")).unwrap();
+        assert!(manifest[0].path.is_absolute());
+
+        // Scanned through a different spelling of the same directory.
+        let mut recorder = HashRecorder::new();
+        let outcome = Scanner::new(photos.join("../photos")).scan(&mut recorder);
+        let merged = merge_manifest(outcome.analysis, &recorder.into_entries(), &manifest);
+        assert!(merged.groups.is_empty(), "{merged:?}");
+    }
+}
@@ -0,0 +1,129 @@
+//! Which filesystem (mount) files live on.
+//!
+//! Hard links only work within a single filesystem, so knowing the mount point
+//! of every copy in a [`DuplicateGroup`] tells whether the group could be
+//! linked together instead of deleted.
+
+use crate::DuplicateGroup;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Finds mount points, caching the answer for every directory it has seen.
+#[derive(Debug, Default)]
+pub struct MountResolver {
+    cache: HashMap<PathBuf, PathBuf>,
+}
+
+impl MountResolver {
+    /// Create a resolver with an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The mount point of the filesystem holding `path`.
+    ///
+    /// On Unix this is the closest ancestor of the canonical path whose parent
+    /// lives on a different device. Elsewhere it is the path's root (the
+    /// drive, on Windows).
+    pub fn mount_point(&mut self, path: &Path) -> io::Result<PathBuf> {
+        let canonical = fs::canonicalize(path)?;
+        let dir = if canonical.is_dir() {
+            canonical.as_path()
+        } else {
+            canonical.parent().unwrap_or(&canonical)
+        };
+        if let Some(mount) = self.cache.get(dir) {
+            return Ok(mount.clone());
+        }
+        let mount = find_mount_point(dir)?;
+        self.cache.insert(dir.to_path_buf(), mount.clone());
+        Ok(mount)
+    }
+
+    /// The distinct mount points of every file in `group`, sorted. Files that
+    /// can no longer be read are left out.
+    pub fn group_mounts(&mut self, group: &DuplicateGroup) -> Vec<PathBuf> {
+        std::iter::once(&group.keep)
+            .chain(&group.dupes)
+            .filter_map(|path| self.mount_point(path).ok())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(unix)]
+fn find_mount_point(dir: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    mount_point_by_device(dir, |path| Ok(fs::metadata(path)?.dev()))
+}
+
+/// The closest ancestor of `dir` (or `dir` itself) whose parent has a
+/// different device id according to `device_of`.
+#[cfg(unix)]
+fn mount_point_by_device(
+    dir: &Path,
+    device_of: impl Fn(&Path) -> io::Result<u64>,
+) -> io::Result<PathBuf> {
+    let device = device_of(dir)?;
+    let mut mount = dir;
+    while let Some(parent) = mount.parent() {
+        if device_of(parent)? != device {
+            break;
+        }
+        mount = parent;
+    }
+    Ok(mount.to_path_buf())
+}
+
+#[cfg(not(unix))]
+fn find_mount_point(dir: &Path) -> io::Result<PathBuf> {
+    Ok(dir.ancestors().last().unwrap_or(dir).to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn files_in_one_directory_share_a_mount() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a"), b"x").unwrap();
+        fs::write(dir.path().join("sub/b"), b"x").unwrap();
+
+        let group = DuplicateGroup {
+            hash: "x".into(),
+            size: 1,
+            keep: dir.path().join("a"),
+            dupes: vec![dir.path().join("sub/b")],
+        };
+        let mut resolver = MountResolver::new();
+        let mounts = resolver.group_mounts(&group);
+
+        assert_eq!(mounts.len(), 1);
+        let canonical = fs::canonicalize(dir.path()).unwrap();
+        assert!(canonical.starts_with(&mounts[0]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_subdirectory_on_another_device_is_its_own_mount() {
+        let root = Path::new("/data");
+        let device_of = |path: &Path| Ok(if path.starts_with("/data/usb") { 2 } else { 1 });
+
+        assert_eq!(
+            mount_point_by_device(&root.join("usb/photos"), device_of).unwrap(),
+            root.join("usb")
+        );
+        assert_eq!(
+            mount_point_by_device(&root.join("docs"), device_of).unwrap(),
+            Path::new("/")
+        );
+    }
+}
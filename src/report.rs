@@ -0,0 +1,829 @@
+//! Report formats for a finished [`DuplicateAnalysis`].
+//!
+//! Writers here take any [`Write`] so they can target files, stdout or
+//! in-memory buffers in tests. Per-file metadata that is not part of the
+//! analysis (such as modification times) is looked up when the report is
+//! written.
+
+use crate::{DuplicateAnalysis, DuplicateGroup, format_bytes};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Version of the JSON report layout, bumped on incompatible changes.
+///
+/// The schema is published as `docs/report.schema.json`.
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+/// Role of a file within its duplicate group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileRole {
+    /// The copy that is kept.
+    Keep,
+    /// A copy that is a candidate for deletion.
+    Dupe,
+}
+
+impl FileRole {
+    /// Lower-case label used in machine-readable reports.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileRole::Keep => "keep",
+            FileRole::Dupe => "dupe",
+        }
+    }
+}
+
+/// Format a modification time as an RFC 3339 UTC timestamp (second precision).
+pub fn format_timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// Modification time of `path` as an RFC 3339 timestamp, if it can be read.
+pub fn modified_timestamp(path: &Path) -> Option<String> {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .map(format_timestamp)
+}
+
+/// Every file in `group` with its role, the kept file first.
+fn files_with_roles(group: &DuplicateGroup) -> impl Iterator<Item = (FileRole, &PathBuf)> {
+    std::iter::once((FileRole::Keep, &group.keep))
+        .chain(group.dupes.iter().map(|p| (FileRole::Dupe, p)))
+}
+
+/// How and when a report was produced, recorded alongside the analysis.
+#[derive(Debug, Clone)]
+pub struct ReportContext {
+    /// Root directories that were scanned.
+    pub roots: Vec<PathBuf>,
+    /// Output mode ddupe ran in (for example `json`).
+    pub mode: String,
+    /// Whether the run was a dry run (no deletions).
+    pub dry_run: bool,
+    /// Whether interactive mode was requested.
+    pub interactive: bool,
+    /// When the scan started.
+    pub started_at: SystemTime,
+    /// When the scan finished.
+    pub finished_at: SystemTime,
+}
+
+/// Top-level JSON report document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonReport {
+    /// See [`JSON_SCHEMA_VERSION`].
+    pub schema_version: u32,
+    /// Version of ddupe that wrote the report.
+    pub tool_version: String,
+    /// Scan start time (RFC 3339, UTC).
+    pub started_at: String,
+    /// Scan end time (RFC 3339, UTC).
+    pub finished_at: String,
+    /// Root directories that were scanned.
+    pub roots: Vec<String>,
+    /// One entry per duplicate group.
+    pub duplicate_groups: Vec<JsonGroup>,
+    /// Number of files that could be removed.
+    pub removable_count: usize,
+    /// Bytes that would be freed by removing them.
+    pub savings_bytes: u64,
+    /// Whether the run was a dry run.
+    pub dry_run: bool,
+    /// Whether interactive mode was requested.
+    pub interactive: bool,
+    /// Output mode ddupe ran in.
+    pub mode: String,
+}
+
+/// A duplicate group in the JSON report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonGroup {
+    /// SHA-256 digest shared by every file in the group.
+    pub hash: String,
+    /// Size in bytes of each file.
+    pub size: u64,
+    /// Path of the file that is kept.
+    pub keep: String,
+    /// Every file in the group, the kept one first.
+    pub files: Vec<JsonFile>,
+}
+
+/// A single file in a [`JsonGroup`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonFile {
+    /// Path of the file.
+    pub path: String,
+    /// Whether the file is kept or a duplicate.
+    pub role: FileRole,
+    /// Size in bytes, or `null` if the file could not be read when the report
+    /// was written.
+    pub size: Option<u64>,
+    /// Modification time (RFC 3339, UTC), or `null` if unavailable.
+    pub mtime: Option<String>,
+}
+
+impl JsonGroup {
+    /// Build the JSON view of a group, reading per-file metadata from disk.
+    pub fn from_group(group: &DuplicateGroup) -> Self {
+        let files = files_with_roles(group)
+            .map(|(role, path)| {
+                let meta = fs::metadata(path).ok();
+                JsonFile {
+                    path: path.display().to_string(),
+                    role,
+                    size: meta.as_ref().map(|m| m.len()),
+                    mtime: meta.and_then(|m| m.modified().ok()).map(format_timestamp),
+                }
+            })
+            .collect();
+
+        Self {
+            hash: group.hash.clone(),
+            size: group.size,
+            keep: group.keep.display().to_string(),
+            files,
+        }
+    }
+}
+
+impl JsonReport {
+    /// Build a report for `analysis`.
+    pub fn new(analysis: &DuplicateAnalysis, context: &ReportContext) -> Self {
+        Self {
+            schema_version: JSON_SCHEMA_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: format_timestamp(context.started_at),
+            finished_at: format_timestamp(context.finished_at),
+            roots: context
+                .roots
+                .iter()
+                .map(|r| r.display().to_string())
+                .collect(),
+            duplicate_groups: analysis.groups.iter().map(JsonGroup::from_group).collect(),
+            removable_count: analysis.total_dupes(),
+            savings_bytes: analysis.total_saving_bytes,
+            dry_run: context.dry_run,
+            interactive: context.interactive,
+            mode: context.mode.clone(),
+        }
+    }
+}
+
+/// Write `analysis` as a pretty-printed JSON report.
+pub fn write_json(
+    mut writer: impl Write,
+    analysis: &DuplicateAnalysis,
+    context: &ReportContext,
+) -> io::Result<()> {
+    let report = JsonReport::new(analysis, context);
+    serde_json::to_writer_pretty(&mut writer, &report).map_err(io::Error::other)?;
+    writer.flush()
+}
+
+/// One line of an NDJSON stream, tagged by `type`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NdjsonRecord {
+    /// A finalised duplicate group.
+    Group {
+        /// 1-based id, in the order groups were emitted.
+        id: usize,
+        /// The group itself.
+        #[serde(flatten)]
+        group: JsonGroup,
+    },
+    /// Closing record written once the scan has finished.
+    Summary {
+        /// See [`JSON_SCHEMA_VERSION`].
+        schema_version: u32,
+        /// Version of ddupe that wrote the stream.
+        tool_version: String,
+        /// Scan start time (RFC 3339, UTC).
+        started_at: String,
+        /// Scan end time (RFC 3339, UTC).
+        finished_at: String,
+        /// Root directories that were scanned.
+        roots: Vec<String>,
+        /// Number of group records written.
+        group_count: usize,
+        /// Number of files that could be removed.
+        removable_count: usize,
+        /// Bytes that would be freed by removing them.
+        savings_bytes: u64,
+        /// Whether the scan was interrupted before finishing.
+        cancelled: bool,
+    },
+}
+
+/// Streams duplicate groups as newline-delimited JSON.
+///
+/// Each group is written (and flushed) as soon as [`NdjsonWriter::write_group`]
+/// is called, so consumers can start work before a scan finishes. Running
+/// totals are kept for the closing summary record, so nothing else needs to be
+/// held in memory.
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+    group_count: usize,
+    removable_count: usize,
+    savings_bytes: u64,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    /// Wrap `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            group_count: 0,
+            removable_count: 0,
+            savings_bytes: 0,
+        }
+    }
+
+    fn write_record(&mut self, record: &NdjsonRecord) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, record).map_err(io::Error::other)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+
+    /// Write one group record.
+    pub fn write_group(&mut self, group: &DuplicateGroup) -> io::Result<()> {
+        self.group_count += 1;
+        self.removable_count += group.dupes.len();
+        self.savings_bytes += group.reclaimable_bytes();
+        self.write_record(&NdjsonRecord::Group {
+            id: self.group_count,
+            group: JsonGroup::from_group(group),
+        })
+    }
+
+    /// Write the closing summary record and return the inner writer.
+    pub fn finish(mut self, context: &ReportContext, cancelled: bool) -> io::Result<W> {
+        let summary = NdjsonRecord::Summary {
+            schema_version: JSON_SCHEMA_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: format_timestamp(context.started_at),
+            finished_at: format_timestamp(context.finished_at),
+            roots: context
+                .roots
+                .iter()
+                .map(|r| r.display().to_string())
+                .collect(),
+            group_count: self.group_count,
+            removable_count: self.removable_count,
+            savings_bytes: self.savings_bytes,
+            cancelled,
+        };
+        self.write_record(&summary)?;
+        Ok(self.writer)
+    }
+}
+
+/// Write the stable, line-oriented "porcelain" listing.
+///
+/// Each file produces one record of four tab-separated fields:
+/// `group_id`, `role` (`keep`/`dupe`), `size` in bytes and `path`, terminated
+/// by `terminator` (`b'\n'`, or `b'\0'` for paths that may contain newlines).
+/// The path is always the last field and is written verbatim, so consumers
+/// should split each record on at most three tabs.
+pub fn write_porcelain(
+    mut writer: impl Write,
+    analysis: &DuplicateAnalysis,
+    terminator: u8,
+) -> io::Result<()> {
+    for (idx, group) in analysis.groups.iter().enumerate() {
+        for (role, path) in files_with_roles(group) {
+            write!(writer, "{}\t{}\t{}\t", idx + 1, role.as_str(), group.size)?;
+            writer.write_all(path.as_os_str().as_encoded_bytes())?;
+            writer.write_all(&[terminator])?;
+        }
+    }
+    writer.flush()
+}
+
+/// Write duplicate groups the way `fdupes -r` prints them: one path per line,
+/// with a blank line after each group.
+pub fn write_fdupes(mut writer: impl Write, analysis: &DuplicateAnalysis) -> io::Result<()> {
+    for group in &analysis.groups {
+        for (_, path) in files_with_roles(group) {
+            writer.write_all(path.as_os_str().as_encoded_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// Write the one-line summary printed by `fdupes -m`.
+///
+/// Like fdupes, sizes use decimal units: plain bytes below 1024, kilobytes up
+/// to one million bytes and megabytes above that.
+pub fn write_fdupes_summary(
+    mut writer: impl Write,
+    analysis: &DuplicateAnalysis,
+) -> io::Result<()> {
+    if analysis.groups.is_empty() {
+        writeln!(writer, "No duplicates found.\n")?;
+        return writer.flush();
+    }
+
+    let files = analysis.total_dupes();
+    let sets = analysis.groups.len();
+    let bytes = analysis.total_saving_bytes as f64;
+    if bytes < 1024.0 {
+        writeln!(
+            writer,
+            "{files} duplicate files (in {sets} sets), occupying {bytes:.0} bytes.\n"
+        )?;
+    } else if bytes <= 1000.0 * 1000.0 {
+        writeln!(
+            writer,
+            "{files} duplicate files (in {sets} sets), occupying {:.1} kilobytes\n",
+            bytes / 1000.0
+        )?;
+    } else {
+        writeln!(
+            writer,
+            "{files} duplicate files (in {sets} sets), occupying {:.1} megabytes\n",
+            bytes / (1000.0 * 1000.0)
+        )?;
+    }
+    writer.flush()
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write a CSV report with one row per file.
+///
+/// Columns are `group_id,role,path,size,mtime,hash`. Group ids are 1-based and
+/// match the numbering of the terminal output. `mtime` is empty if the file
+/// can no longer be read.
+pub fn write_csv(mut writer: impl Write, analysis: &DuplicateAnalysis) -> io::Result<()> {
+    writeln!(writer, "group_id,role,path,size,mtime,hash")?;
+
+    for (idx, group) in analysis.groups.iter().enumerate() {
+        for (role, path) in files_with_roles(group) {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                idx + 1,
+                role.as_str(),
+                csv_field(&path.display().to_string()),
+                group.size,
+                modified_timestamp(path).unwrap_or_default(),
+                group.hash
+            )?;
+        }
+    }
+
+    writer.flush()
+}
+
+/// Escape text for inclusion in HTML element content or attribute values.
+fn html_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Reclaimable bytes and dupe count per directory containing dupes, largest
+/// first.
+fn reclaimable_by_directory(analysis: &DuplicateAnalysis) -> Vec<(PathBuf, u64, usize)> {
+    let mut by_dir: HashMap<PathBuf, (u64, usize)> = HashMap::new();
+    for group in &analysis.groups {
+        for dupe in &group.dupes {
+            let dir = dupe.parent().map(Path::to_path_buf).unwrap_or_default();
+            let entry = by_dir.entry(dir).or_default();
+            entry.0 += group.size;
+            entry.1 += 1;
+        }
+    }
+
+    let mut rows: Vec<_> = by_dir
+        .into_iter()
+        .map(|(dir, (bytes, count))| (dir, bytes, count))
+        .collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    rows
+}
+
+const HTML_STYLE: &str = "\
+body{font-family:system-ui,-apple-system,\"Segoe UI\",sans-serif;margin:2rem auto;max-width:1100px;padding:0 1rem;color:#1e293b;background:#f8fafc}
+h1{margin-bottom:.2rem}
+.meta{color:#64748b;margin-top:0}
+.totals{display:flex;gap:1rem;flex-wrap:wrap;margin:1.5rem 0}
+.card{background:#fff;border:1px solid #e2e8f0;border-radius:10px;padding:.8rem 1.2rem;min-width:10rem}
+.card b{display:block;font-size:1.4rem}
+table{border-collapse:collapse;width:100%;margin:.5rem 0 1rem}
+th,td{text-align:left;padding:.3rem .6rem;border-bottom:1px solid #e2e8f0;vertical-align:top}
+td.num,th.num{text-align:right;white-space:nowrap}
+td.path{font-family:ui-monospace,monospace;word-break:break-all}
+details{background:#fff;border:1px solid #e2e8f0;border-radius:10px;margin:.5rem 0;padding:.4rem .8rem}
+summary{cursor:pointer;font-weight:600}
+.keep{color:#15803d;font-weight:600}
+.dupe{color:#b91c1c;font-weight:600}
+";
+
+/// Write a single, self-contained HTML page describing `analysis`.
+///
+/// The page includes overall totals, reclaimable bytes per directory and one
+/// collapsible section per group, largest reclaimable group first. Styles are
+/// inlined and no scripts or external assets are referenced, so the file can
+/// be attached to a ticket and opened anywhere.
+pub fn write_html(
+    mut writer: impl Write,
+    analysis: &DuplicateAnalysis,
+    context: &ReportContext,
+) -> io::Result<()> {
+    let roots = context
+        .roots
+        .iter()
+        .map(|r| html_escape(&r.display().to_string()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(
+        writer,
+        "<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">"
+    )?;
+    writeln!(
+        writer,
+        "<title>ddupe report</title>\n<style>\n{HTML_STYLE}</style>"
+    )?;
+    writeln!(writer, "</head>\n<body>")?;
+    writeln!(writer, "<h1>ddupe duplicate report</h1>")?;
+    writeln!(
+        writer,
+        "<p class=\"meta\">Scanned {roots} &middot; finished {} &middot; ddupe {}</p>",
+        format_timestamp(context.finished_at),
+        env!("CARGO_PKG_VERSION")
+    )?;
+
+    writeln!(writer, "<div class=\"totals\">")?;
+    for (label, value) in [
+        ("Duplicate groups", analysis.groups.len().to_string()),
+        ("Removable files", analysis.total_dupes().to_string()),
+        ("Reclaimable", format_bytes(analysis.total_saving_bytes)),
+    ] {
+        writeln!(
+            writer,
+            "<div class=\"card\">{label}<b>{}</b></div>",
+            html_escape(&value)
+        )?;
+    }
+    writeln!(writer, "</div>")?;
+
+    writeln!(writer, "<h2>By directory</h2>")?;
+    writeln!(
+        writer,
+        "<table>\n<tr><th>Directory</th><th class=\"num\">Dupes</th><th class=\"num\">Reclaimable</th></tr>"
+    )?;
+    for (dir, bytes, count) in reclaimable_by_directory(analysis) {
+        writeln!(
+            writer,
+            "<tr><td class=\"path\">{}</td><td class=\"num\">{count}</td><td class=\"num\">{}</td></tr>",
+            html_escape(&dir.display().to_string()),
+            format_bytes(bytes)
+        )?;
+    }
+    writeln!(writer, "</table>")?;
+
+    let mut groups: Vec<(usize, &DuplicateGroup)> = analysis.groups.iter().enumerate().collect();
+    groups.sort_by(|a, b| {
+        b.1.reclaimable_bytes()
+            .cmp(&a.1.reclaimable_bytes())
+            .then_with(|| a.0.cmp(&b.0))
+    });
+
+    writeln!(writer, "<h2>Duplicate groups</h2>")?;
+    for (idx, group) in groups {
+        writeln!(
+            writer,
+            "<details>\n<summary>Group {} &middot; {} reclaimable &middot; {} copies of {}</summary>",
+            idx + 1,
+            format_bytes(group.reclaimable_bytes()),
+            group.dupes.len() + 1,
+            format_bytes(group.size)
+        )?;
+        writeln!(
+            writer,
+            "<table>\n<tr><th>Role</th><th>Path</th><th class=\"num\">Size</th><th>Modified</th></tr>"
+        )?;
+        for (role, path) in files_with_roles(group) {
+            let meta = fs::metadata(path).ok();
+            writeln!(
+                writer,
+                "<tr><td class=\"{role}\">{role}</td><td class=\"path\">{}</td><td class=\"num\">{}</td><td>{}</td></tr>",
+                html_escape(&path.display().to_string()),
+                meta.as_ref()
+                    .map(|m| format_bytes(m.len()))
+                    .unwrap_or_default(),
+                meta.and_then(|m| m.modified().ok())
+                    .map(format_timestamp)
+                    .unwrap_or_default(),
+                role = role.as_str(),
+            )?;
+        }
+        writeln!(
+            writer,
+            "</table>\n<p class=\"meta\">SHA-256 {}</p>\n</details>",
+            html_escape(&group.hash)
+        )?;
+    }
+
+    writeln!(writer, "</body>\n</html>")?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyse_duplicates;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("plain/path.txt"), "plain/path.txt");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn write_csv_emits_one_row_per_file() {
+        let dir = TempDir::new().unwrap();
+        let keep = dir.path().join("a.txt");
+        let dupe = dir.path().join("b,c.txt");
+        fs::write(&keep, b"xy").unwrap();
+        fs::write(&dupe, b"xy").unwrap();
+
+        let mut map = HashMap::new();
+        map.insert("h1".to_string(), vec![dupe.clone(), keep.clone()]);
+        let analysis = analyse_duplicates(map);
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &analysis).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "group_id,role,path,size,mtime,hash");
+        assert!(lines[1].starts_with(&format!("1,keep,{},2,", keep.display())));
+        assert!(lines[1].ends_with(",h1"));
+        assert!(lines[2].starts_with(&format!("1,dupe,\"{}\",2,", dupe.display())));
+    }
+
+    #[test]
+    fn write_csv_leaves_mtime_empty_for_missing_files() {
+        let analysis = DuplicateAnalysis {
+            groups: vec![DuplicateGroup {
+                hash: "h".to_string(),
+                size: 0,
+                keep: "/nonexistent/keep".into(),
+                dupes: vec!["/nonexistent/dupe".into()],
+            }],
+            removable_files: vec!["/nonexistent/dupe".into()],
+            total_saving_bytes: 0,
+        };
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &analysis).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains("1,dupe,/nonexistent/dupe,0,,h\n"));
+    }
+
+    #[test]
+    fn ndjson_writer_streams_groups_then_summary() {
+        let group = DuplicateGroup {
+            hash: "h".to_string(),
+            size: 5,
+            keep: "/x/keep".into(),
+            dupes: vec!["/x/one".into(), "/x/two".into()],
+        };
+        let now = SystemTime::now();
+        let context = ReportContext {
+            roots: vec!["/x".into()],
+            mode: "ndjson".to_string(),
+            dry_run: true,
+            interactive: false,
+            started_at: now,
+            finished_at: now,
+        };
+
+        let mut writer = NdjsonWriter::new(Vec::new());
+        writer.write_group(&group).unwrap();
+        let out = writer.finish(&context, false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["type"], "group");
+        assert_eq!(lines[0]["id"], 1);
+        assert_eq!(lines[0]["hash"], "h");
+        assert_eq!(lines[0]["files"].as_array().unwrap().len(), 3);
+        assert_eq!(lines[1]["type"], "summary");
+        assert_eq!(lines[1]["group_count"], 1);
+        assert_eq!(lines[1]["removable_count"], 2);
+        assert_eq!(lines[1]["savings_bytes"], 10);
+        assert_eq!(lines[1]["cancelled"], false);
+    }
+
+    #[test]
+    fn write_porcelain_emits_tab_separated_records() {
+        let analysis = DuplicateAnalysis {
+            groups: vec![DuplicateGroup {
+                hash: "h".to_string(),
+                size: 7,
+                keep: "/x/keep".into(),
+                dupes: vec!["/x/new\nline".into()],
+            }],
+            removable_files: vec![],
+            total_saving_bytes: 7,
+        };
+
+        let mut out = Vec::new();
+        write_porcelain(&mut out, &analysis, b'\n').unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1\tkeep\t7\t/x/keep\n1\tdupe\t7\t/x/new\nline\n"
+        );
+
+        let mut out = Vec::new();
+        write_porcelain(&mut out, &analysis, b'\0').unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1\tkeep\t7\t/x/keep\x001\tdupe\t7\t/x/new\nline\x00"
+        );
+    }
+
+    fn sample_analysis(total_saving_bytes: u64) -> DuplicateAnalysis {
+        DuplicateAnalysis {
+            groups: vec![
+                DuplicateGroup {
+                    hash: "h1".to_string(),
+                    size: 1,
+                    keep: "/x/a".into(),
+                    dupes: vec!["/x/b".into(), "/x/c".into()],
+                },
+                DuplicateGroup {
+                    hash: "h2".to_string(),
+                    size: 1,
+                    keep: "/y/a".into(),
+                    dupes: vec!["/y/b".into()],
+                },
+            ],
+            removable_files: vec!["/x/b".into(), "/x/c".into(), "/y/b".into()],
+            total_saving_bytes,
+        }
+    }
+
+    #[test]
+    fn write_fdupes_separates_groups_with_blank_lines() {
+        let mut out = Vec::new();
+        write_fdupes(&mut out, &sample_analysis(3)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "/x/a\n/x/b\n/x/c\n\n/y/a\n/y/b\n\n"
+        );
+    }
+
+    #[test]
+    fn write_fdupes_summary_matches_fdupes_units() {
+        let summary = |bytes| {
+            let mut out = Vec::new();
+            write_fdupes_summary(&mut out, &sample_analysis(bytes)).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            summary(3),
+            "3 duplicate files (in 2 sets), occupying 3 bytes.\n\n"
+        );
+        assert_eq!(
+            summary(2500),
+            "3 duplicate files (in 2 sets), occupying 2.5 kilobytes\n\n"
+        );
+        assert_eq!(
+            summary(4_200_000),
+            "3 duplicate files (in 2 sets), occupying 4.2 megabytes\n\n"
+        );
+
+        let mut out = Vec::new();
+        write_fdupes_summary(&mut out, &DuplicateAnalysis::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "No duplicates found.\n\n");
+    }
+
+    #[test]
+    fn html_escape_handles_markup_characters() {
+        assert_eq!(
+            html_escape("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn write_html_orders_groups_by_reclaimable_bytes() {
+        let small = DuplicateGroup {
+            hash: "small".to_string(),
+            size: 10,
+            keep: "/a/keep-small".into(),
+            dupes: vec!["/b/dupe-small".into()],
+        };
+        let large = DuplicateGroup {
+            hash: "large".to_string(),
+            size: 100,
+            keep: "/a/keep-<large>".into(),
+            dupes: vec!["/b/dupe-large".into(), "/c/dupe-large".into()],
+        };
+        let analysis = DuplicateAnalysis {
+            groups: vec![small, large],
+            removable_files: vec![],
+            total_saving_bytes: 210,
+        };
+        let now = SystemTime::now();
+        let context = ReportContext {
+            roots: vec!["/".into()],
+            mode: "html".to_string(),
+            dry_run: true,
+            interactive: false,
+            started_at: now,
+            finished_at: now,
+        };
+
+        let mut out = Vec::new();
+        write_html(&mut out, &analysis, &context).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        let large_pos = html.find("Group 2 &middot; 200 B reclaimable").unwrap();
+        let small_pos = html.find("Group 1 &middot; 10 B reclaimable").unwrap();
+        assert!(large_pos < small_pos);
+        assert!(html.contains("/a/keep-&lt;large&gt;"));
+        assert!(html.contains("<td class=\"path\">/b</td><td class=\"num\">2</td>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("http"));
+    }
+
+    #[test]
+    fn json_report_carries_group_and_file_metadata() {
+        let dir = TempDir::new().unwrap();
+        let keep = dir.path().join("a.txt");
+        let dupe = dir.path().join("b.txt");
+        fs::write(&keep, b"abc").unwrap();
+        fs::write(&dupe, b"abc").unwrap();
+
+        let mut map = HashMap::new();
+        map.insert("h1".to_string(), vec![dupe.clone(), keep.clone()]);
+        let analysis = analyse_duplicates(map);
+        let now = SystemTime::now();
+        let context = ReportContext {
+            roots: vec![dir.path().to_path_buf()],
+            mode: "json".to_string(),
+            dry_run: true,
+            interactive: false,
+            started_at: now,
+            finished_at: now,
+        };
+
+        let report = JsonReport::new(&analysis, &context);
+
+        assert_eq!(report.schema_version, JSON_SCHEMA_VERSION);
+        assert_eq!(report.tool_version, env!("CARGO_PKG_VERSION"));
+        let group = &report.duplicate_groups[0];
+        assert_eq!(group.hash, "h1");
+        assert_eq!(group.size, 3);
+        assert_eq!(group.keep, keep.display().to_string());
+        assert_eq!(group.files.len(), 2);
+        assert_eq!(group.files[0].role, FileRole::Keep);
+        assert_eq!(group.files[1].role, FileRole::Dupe);
+        assert_eq!(group.files[1].size, Some(3));
+        assert!(group.files[1].mtime.is_some());
+    }
+}
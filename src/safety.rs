@@ -0,0 +1,369 @@
+//! Safety rails around deletion.
+//!
+//! [`ProtectedPaths`] is a list of glob patterns for paths that must never be
+//! deleted, whatever the analysis says. It is applied twice: once to take
+//! protected files out of the plan, and once more as a final check on the plan
+//! just before anything is touched.
+//!
+//! The other checks are circuit breakers for a plan that is bigger or wider
+//! than intended: [`DeletionLimits`] caps how much a plan may remove,
+//! [`first_outside_roots`] catches paths that are not under a scanned root,
+//! and [`FailureBreaker`] stops a run once deletions keep failing.
+
+use crate::{DuplicateAnalysis, DuplicateGroup, format_bytes};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::{
+    collections::HashSet,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Upper bounds on a deletion plan. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeletionLimits {
+    /// Most files a plan may delete.
+    pub max_count: Option<u64>,
+    /// Most bytes a plan may free.
+    pub max_bytes: Option<u64>,
+}
+
+/// A plan that is over one of its [`DeletionLimits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// The plan deletes `planned` files; at most `limit` are allowed.
+    Count { planned: u64, limit: u64 },
+    /// The plan frees `planned` bytes; at most `limit` are allowed.
+    Bytes { planned: u64, limit: u64 },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Count { planned, limit } => write!(
+                f,
+                "the plan deletes {planned} file(s), more than the limit of {limit}"
+            ),
+            Self::Bytes { planned, limit } => write!(
+                f,
+                "the plan frees {}, more than the limit of {}",
+                format_bytes(*planned),
+                format_bytes(*limit)
+            ),
+        }
+    }
+}
+
+impl DeletionLimits {
+    /// Check a plan deleting `count` files totalling `bytes`.
+    pub fn check(&self, count: u64, bytes: u64) -> Result<(), LimitExceeded> {
+        if let Some(limit) = self.max_count
+            && count > limit
+        {
+            return Err(LimitExceeded::Count {
+                planned: count,
+                limit,
+            });
+        }
+        if let Some(limit) = self.max_bytes
+            && bytes > limit
+        {
+            return Err(LimitExceeded::Bytes {
+                planned: bytes,
+                limit,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The first planned path that is not inside one of `roots`.
+///
+/// Paths are compared as absolute paths with `.` and `..` resolved lexically,
+/// so `root/../elsewhere` counts as outside.
+pub fn first_outside_roots<'a>(
+    plan: impl IntoIterator<Item = &'a PathBuf>,
+    roots: &[PathBuf],
+) -> Option<&'a PathBuf> {
+    let roots: Vec<PathBuf> = roots.iter().filter_map(|r| normalise(r)).collect();
+    plan.into_iter().find(|path| {
+        normalise(path).is_none_or(|path| !roots.iter().any(|root| path.starts_with(root)))
+    })
+}
+
+/// Absolute form of `path` with `.` and `..` components resolved.
+fn normalise(path: &Path) -> Option<PathBuf> {
+    let mut normalised = PathBuf::new();
+    for component in std::path::absolute(path).ok()?.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalised.pop();
+            }
+            other => normalised.push(other),
+        }
+    }
+    Some(normalised)
+}
+
+/// Counts failed deletions and trips once a threshold is reached.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FailureBreaker {
+    limit: Option<u64>,
+    failures: u64,
+}
+
+impl FailureBreaker {
+    /// Trip after `limit` failures; `None` never trips.
+    pub fn new(limit: Option<u64>) -> Self {
+        Self { limit, failures: 0 }
+    }
+
+    /// Record one failure. Returns whether the breaker has now tripped.
+    pub fn record_failure(&mut self) -> bool {
+        self.failures += 1;
+        self.tripped()
+    }
+
+    /// Number of failures recorded so far.
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// Whether the threshold has been reached.
+    pub fn tripped(&self) -> bool {
+        self.limit.is_some_and(|limit| self.failures >= limit)
+    }
+}
+
+/// Glob patterns (and exact paths) for paths that are never deleted.
+///
+/// A path is protected when it, or any directory above it, matches one of the
+/// patterns, so `/etc` protects everything under `/etc` and `**/.ssh` protects
+/// every `.ssh` directory with its contents. Paths are matched both as given
+/// and as absolute paths. Paths added with [`ProtectedPaths::add_paths`] are
+/// protected exactly, whether a path names them as absolute or relative, and
+/// with or without `.` and `..` components.
+#[derive(Debug, Clone)]
+pub struct ProtectedPaths {
+    set: GlobSet,
+    patterns: Vec<String>,
+    paths: HashSet<PathBuf>,
+}
+
+impl Default for ProtectedPaths {
+    fn default() -> Self {
+        Self {
+            set: GlobSet::empty(),
+            patterns: Vec::new(),
+            paths: HashSet::new(),
+        }
+    }
+}
+
+impl ProtectedPaths {
+    /// Compile `patterns`. An invalid pattern is an `InvalidInput` error.
+    pub fn new(patterns: &[String]) -> io::Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = Glob::new(pattern).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid protect pattern '{pattern}': {e}"),
+                )
+            })?;
+            builder.add(glob);
+        }
+        let set = builder
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(Self {
+            set,
+            patterns: patterns.to_vec(),
+            paths: HashSet::new(),
+        })
+    }
+
+    /// Also protect each of `paths`, such as files listed in a manifest that
+    /// ddupe cannot reach.
+    pub fn add_paths(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        self.paths.extend(
+            paths
+                .into_iter()
+                .map(|path| normalise(&path).unwrap_or(path)),
+        );
+    }
+
+    /// The patterns this list was built from.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Whether no patterns or paths were given.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.paths.is_empty()
+    }
+
+    /// Whether `path` was added, or it or any directory above it
+    /// matches a pattern.
+    pub fn is_protected(&self, path: &Path) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        if !self.paths.is_empty()
+            && self
+                .paths
+                .contains(&normalise(path).unwrap_or_else(|| path.to_path_buf()))
+        {
+            return true;
+        }
+        let absolute = std::path::absolute(path).ok();
+        std::iter::once(path)
+            .chain(absolute.as_deref())
+            .any(|p| p.ancestors().any(|a| self.set.is_match(a)))
+    }
+
+    /// Whether removing `dir` recursively would touch a protected path: `dir`
+    /// itself is protected, or something inside it is.
+    pub fn protects_within(&self, dir: &Path) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        self.is_protected(dir)
+            || walkdir::WalkDir::new(dir)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .any(|entry| self.is_protected(entry.path()))
+    }
+
+    /// Take protected files out of `analysis`, so they are never removable.
+    ///
+    /// A protected file that would have been a dupe stays in place; groups left
+    /// without anything deletable are dropped and the totals recomputed.
+    pub fn apply(&self, analysis: DuplicateAnalysis) -> DuplicateAnalysis {
+        if self.is_empty() {
+            return analysis;
+        }
+        let groups: Vec<DuplicateGroup> = analysis
+            .groups
+            .into_iter()
+            .filter_map(|group| {
+                let dupes: Vec<PathBuf> = group
+                    .dupes
+                    .iter()
+                    .filter(|p| !self.is_protected(p))
+                    .cloned()
+                    .collect();
+                (!dupes.is_empty()).then_some(DuplicateGroup { dupes, ..group })
+            })
+            .collect();
+
+        DuplicateAnalysis {
+            removable_files: groups
+                .iter()
+                .flat_map(|g| g.dupes.iter().cloned())
+                .collect(),
+            total_saving_bytes: groups.iter().map(|g| g.reclaimable_bytes()).sum(),
+            groups,
+        }
+    }
+
+    /// Final check on a deletion plan: the first planned path that is (or,
+    /// for directories, contains) something protected.
+    pub fn first_violation<'a>(
+        &self,
+        plan: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> Option<&'a PathBuf> {
+        plan.into_iter().find(|path| self.protects_within(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(keep: &str, dupes: &[&str]) -> DuplicateGroup {
+        DuplicateGroup {
+            hash: keep.to_string(),
+            size: 10,
+            keep: keep.into(),
+            dupes: dupes.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn protected_files_are_never_removable() {
+        let protect = ProtectedPaths::new(&["/etc".to_string(), "**/.ssh".to_string()]).unwrap();
+        assert!(protect.is_protected(Path::new("/etc/passwd")));
+        assert!(protect.is_protected(Path::new("/home/me/.ssh/id_ed25519")));
+        assert!(!protect.is_protected(Path::new("/home/me/notes")));
+
+        let analysis = DuplicateAnalysis {
+            groups: vec![
+                group("/backup/passwd", &["/etc/passwd"]),
+                group("/a/key", &["/home/me/.ssh/key", "/tmp/key"]),
+            ],
+            ..DuplicateAnalysis::default()
+        };
+        let safe = protect.apply(analysis);
+
+        assert_eq!(safe.groups.len(), 1);
+        assert_eq!(safe.removable_files, [PathBuf::from("/tmp/key")]);
+        assert_eq!(safe.total_saving_bytes, 10);
+
+        let plan = [PathBuf::from("/tmp/key"), PathBuf::from("/etc/hosts")];
+        assert_eq!(protect.first_violation(&plan), Some(&plan[1]));
+    }
+
+    #[test]
+    fn circuit_breakers_catch_oversized_or_stray_plans() {
+        let limits = DeletionLimits {
+            max_count: Some(2),
+            max_bytes: Some(100),
+        };
+        assert_eq!(limits.check(2, 100), Ok(()));
+        assert_eq!(
+            limits.check(3, 10),
+            Err(LimitExceeded::Count {
+                planned: 3,
+                limit: 2
+            })
+        );
+        assert!(matches!(
+            limits.check(1, 101),
+            Err(LimitExceeded::Bytes { .. })
+        ));
+
+        let roots = [PathBuf::from("/srv/share")];
+        let plan = [
+            PathBuf::from("/srv/share/a"),
+            PathBuf::from("/srv/share/../other/b"),
+        ];
+        assert_eq!(first_outside_roots(&plan, &roots), Some(&plan[1]));
+        assert_eq!(first_outside_roots(&plan[..1], &roots), None);
+
+        let mut breaker = FailureBreaker::new(Some(2));
+        assert!(!breaker.record_failure());
+        assert!(breaker.record_failure());
+        assert_eq!(breaker.failures(), 2);
+        assert!(!FailureBreaker::new(None).record_failure());
+    }
+
+    #[test]
+    fn added_paths_are_protected_however_they_are_spelled() {
+        let mut protect = ProtectedPaths::default();
+        protect.add_paths([PathBuf::from("/offline/a.jpg")]);
+        assert!(protect.is_protected(Path::new("/offline/a.jpg")));
+        assert!(protect.is_protected(Path::new("/offline/../offline/./a.jpg")));
+        assert!(!protect.is_protected(Path::new("/offline/b.jpg")));
+
+        let relative = PathBuf::from("photos/a.jpg");
+        protect.add_paths([std::path::absolute(&relative).unwrap()]);
+        assert!(protect.is_protected(&relative));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let err = ProtectedPaths::new(&["a/[".to_string()]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,987 @@
+//! Full scans with progress callbacks.
+//!
+//! [`Scanner`] ties together walking, hashing and duplicate analysis, and
+//! reports what it is doing through a [`ScanObserver`]. Front-ends (the CLI, a
+//! GUI) implement the observer to drive their own progress display instead of
+//! re-implementing the hashing loop. A [`CancellationToken`] lets a caller
+//! stop a long scan cooperatively, and a checkpoint file lets a later scan
+//! pick up where an interrupted one left off.
+
+use crate::{
+    DuplicateAnalysis, DuplicateGroup, FileTypeFilter, GroupOrder, WalkOptions, analyse_duplicates,
+    checkpoint::{Checkpoint, FileRecord, FileStamp},
+    hash_file_with_progress,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// How often a checkpoint is written while hashing, by default.
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// SHA-256 of zero bytes: the hash every empty file shares.
+pub(crate) const EMPTY_SHA256: &str =
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// What [`Scanner::scan`] does with zero-byte files.
+///
+/// Every empty file has the same content, so treating them as duplicates
+/// lumps unrelated placeholders (`.gitkeep`, lock files, markers) into one
+/// huge group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyFilePolicy {
+    /// Leave empty files out of the analysis entirely.
+    #[default]
+    Ignore,
+    /// Leave empty files out of the analysis, but list them in
+    /// [`ScanOutcome::empty_files`].
+    Report,
+    /// Treat empty files like any other file, grouping them as duplicates.
+    Include,
+}
+
+/// A cheap, cloneable flag used to request that a long-running operation stop.
+///
+/// Work loops check the token between items (files walked, hashed or deleted),
+/// so an operation always finishes the item it is working on before stopping.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation. Every clone of this token observes it.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Callbacks invoked by [`Scanner::scan`] as a scan progresses.
+///
+/// Every method has an empty default implementation, so implementors only need
+/// to override the events they care about.
+pub trait ScanObserver {
+    /// A root directory is about to be walked.
+    fn walk_started(&mut self, _root: &Path) {}
+
+    /// A file was found during the walk and will be hashed.
+    fn file_discovered(&mut self, _path: &Path) {}
+
+    /// Walking has finished; `total_files` files will now be hashed.
+    fn walk_finished(&mut self, _total_files: usize) {}
+
+    /// Hashing of `path` is about to start.
+    fn hashing_started(&mut self, _path: &Path) {}
+
+    /// Hashing of `path` completed successfully.
+    fn hashing_finished(&mut self, _path: &Path, _hash: &str) {}
+
+    /// `bytes` more bytes were read from the file currently being hashed.
+    fn bytes_processed(&mut self, _bytes: u64) {}
+
+    /// A duplicate group was identified.
+    fn group_found(&mut self, _group: &DuplicateGroup) {}
+
+    /// Something went wrong while walking or hashing `path`. The scan carries
+    /// on without it.
+    fn error(&mut self, _path: &Path, _error: &io::Error) {}
+}
+
+/// Forward events through a mutable reference.
+impl<T: ScanObserver + ?Sized> ScanObserver for &mut T {
+    fn walk_started(&mut self, root: &Path) {
+        (**self).walk_started(root);
+    }
+    fn file_discovered(&mut self, path: &Path) {
+        (**self).file_discovered(path);
+    }
+    fn walk_finished(&mut self, total_files: usize) {
+        (**self).walk_finished(total_files);
+    }
+    fn hashing_started(&mut self, path: &Path) {
+        (**self).hashing_started(path);
+    }
+    fn hashing_finished(&mut self, path: &Path, hash: &str) {
+        (**self).hashing_finished(path, hash);
+    }
+    fn bytes_processed(&mut self, bytes: u64) {
+        (**self).bytes_processed(bytes);
+    }
+    fn group_found(&mut self, group: &DuplicateGroup) {
+        (**self).group_found(group);
+    }
+    fn error(&mut self, path: &Path, error: &io::Error) {
+        (**self).error(path, error);
+    }
+}
+
+/// Send every event to both observers, first `A` then `B`.
+impl<A: ScanObserver, B: ScanObserver> ScanObserver for (A, B) {
+    fn walk_started(&mut self, root: &Path) {
+        self.0.walk_started(root);
+        self.1.walk_started(root);
+    }
+    fn file_discovered(&mut self, path: &Path) {
+        self.0.file_discovered(path);
+        self.1.file_discovered(path);
+    }
+    fn walk_finished(&mut self, total_files: usize) {
+        self.0.walk_finished(total_files);
+        self.1.walk_finished(total_files);
+    }
+    fn hashing_started(&mut self, path: &Path) {
+        self.0.hashing_started(path);
+        self.1.hashing_started(path);
+    }
+    fn hashing_finished(&mut self, path: &Path, hash: &str) {
+        self.0.hashing_finished(path, hash);
+        self.1.hashing_finished(path, hash);
+    }
+    fn bytes_processed(&mut self, bytes: u64) {
+        self.0.bytes_processed(bytes);
+        self.1.bytes_processed(bytes);
+    }
+    fn group_found(&mut self, group: &DuplicateGroup) {
+        self.0.group_found(group);
+        self.1.group_found(group);
+    }
+    fn error(&mut self, path: &Path, error: &io::Error) {
+        self.0.error(path, error);
+        self.1.error(path, error);
+    }
+}
+
+/// An observer that ignores every event.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopObserver;
+
+impl ScanObserver for NoopObserver {}
+
+/// Result of a [`Scanner::scan`] run.
+#[derive(Debug, Clone)]
+pub struct ScanOutcome {
+    /// Duplicate analysis over every file that was hashed successfully.
+    pub analysis: DuplicateAnalysis,
+    /// Number of files discovered by the walk.
+    pub files_scanned: usize,
+    /// Whether the scan was stopped early through its [`CancellationToken`].
+    /// When set, `analysis` only covers the files hashed before stopping.
+    pub cancelled: bool,
+    /// Zero-byte files, sorted by path. Only filled in with
+    /// [`EmptyFilePolicy::Report`].
+    pub empty_files: Vec<PathBuf>,
+    /// Files reached through a symlink (`--type any`, or only via a followed
+    /// symlink) whose content matches a different scanned regular file,
+    /// paired with that file and sorted. They are never offered for deletion.
+    pub linked_copies: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Files found by [`Scanner::walk`].
+#[derive(Default)]
+struct Walked {
+    /// Every regular file with its size.
+    files: Vec<(PathBuf, u64)>,
+    /// Files whose path goes through a symlink. They are hashed but never
+    /// offered for deletion.
+    linked: HashSet<PathBuf>,
+}
+
+/// Take files reached through a symlink out of `analysis`, so they are never
+/// deleted. A group keeps a real file where it has one, and is dropped once no
+/// deletable duplicates remain.
+///
+/// Also returns each linked path that has the same content as a different
+/// real file, paired with that file, for [`ScanOutcome::linked_copies`].
+fn keep_linked_files(
+    analysis: DuplicateAnalysis,
+    linked: &HashSet<PathBuf>,
+) -> (DuplicateAnalysis, Vec<(PathBuf, PathBuf)>) {
+    let mut linked_copies = Vec::new();
+    let groups: Vec<DuplicateGroup> = analysis
+        .groups
+        .into_iter()
+        .filter_map(|group| {
+            let (mut real, links): (Vec<PathBuf>, Vec<PathBuf>) = std::iter::once(group.keep)
+                .chain(group.dupes)
+                .partition(|path| !linked.contains(path));
+            if let Some(first) = real.first() {
+                // A link to one of the real files is that file, not a copy.
+                let targets: HashSet<PathBuf> = real
+                    .iter()
+                    .filter_map(|p| fs::canonicalize(p).ok())
+                    .collect();
+                linked_copies.extend(
+                    links
+                        .into_iter()
+                        .filter(|link| fs::canonicalize(link).is_ok_and(|t| !targets.contains(&t)))
+                        .map(|link| (link, first.clone())),
+                );
+            }
+            if real.len() < 2 {
+                return None;
+            }
+            let keep = real.remove(0);
+            Some(DuplicateGroup {
+                keep,
+                dupes: real,
+                ..group
+            })
+        })
+        .collect();
+
+    let analysis = DuplicateAnalysis {
+        removable_files: groups
+            .iter()
+            .flat_map(|g| g.dupes.iter().cloned())
+            .collect(),
+        total_saving_bytes: groups.iter().map(|g| g.reclaimable_bytes()).sum(),
+        groups,
+    };
+    (analysis, linked_copies)
+}
+
+/// Walks one or more roots, hashes every file and analyses duplicates.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    roots: Vec<PathBuf>,
+    cancel: CancellationToken,
+    checkpoint: Option<PathBuf>,
+    checkpoint_interval: Duration,
+    empty_files: EmptyFilePolicy,
+    walk: WalkOptions,
+    collect_groups: bool,
+}
+
+impl Scanner {
+    /// Create a scanner for a single root directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            roots: vec![root.into()],
+            cancel: CancellationToken::new(),
+            checkpoint: None,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            empty_files: EmptyFilePolicy::default(),
+            walk: WalkOptions::default(),
+            collect_groups: true,
+        }
+    }
+
+    /// Add another root directory to scan.
+    pub fn add_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    /// Stop walking and hashing once `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Resume from, and periodically save progress to, the checkpoint at `path`.
+    ///
+    /// If the file exists, files it records with unchanged size and
+    /// modification time reuse their stored hash. Progress is written back
+    /// every [`DEFAULT_CHECKPOINT_INTERVAL`] (see
+    /// [`Scanner::with_checkpoint_interval`]) and once more when the scan ends,
+    /// including when it is cancelled.
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Change how often the checkpoint is written while hashing.
+    pub fn with_checkpoint_interval(mut self, interval: Duration) -> Self {
+        self.checkpoint_interval = interval;
+        self
+    }
+
+    /// Choose how zero-byte files are handled. Defaults to
+    /// [`EmptyFilePolicy::Ignore`].
+    pub fn with_empty_files(mut self, policy: EmptyFilePolicy) -> Self {
+        self.empty_files = policy;
+        self
+    }
+
+    /// Follow symlinks to files and directories while walking.
+    ///
+    /// By default symlinks are skipped entirely. When following them, a file
+    /// reachable by several paths is hashed once, so a link is never reported
+    /// as a duplicate of its own target, and symlink loops are reported as
+    /// errors instead of being walked forever. Files only reachable through a
+    /// symlink are hashed but never offered for deletion.
+    pub fn with_follow_symlinks(mut self, follow: bool) -> Self {
+        self.walk.follow_symlinks = follow;
+        self
+    }
+
+    /// Stay on the filesystem each root lives on, skipping any directory that
+    /// is a mount point for another one (network shares, FUSE mounts, ...).
+    pub fn with_one_file_system(mut self, one_file_system: bool) -> Self {
+        self.walk.one_file_system = one_file_system;
+        self
+    }
+
+    /// Only descend `depth` directory levels below each root.
+    pub fn with_max_depth(mut self, depth: Option<usize>) -> Self {
+        self.walk.max_depth = depth;
+        self
+    }
+
+    /// Skip hidden (dot) files and directories.
+    pub fn with_skip_hidden(mut self, skip: bool) -> Self {
+        self.walk.skip_hidden = skip;
+        self
+    }
+
+    /// Choose which kinds of directory entry are hashed.
+    pub fn with_file_types(mut self, filter: FileTypeFilter) -> Self {
+        self.walk.file_types = filter;
+        self
+    }
+
+    /// Whether to keep the duplicate groups in [`ScanOutcome::analysis`].
+    ///
+    /// With `false`, groups are only passed to [`ScanObserver::group_found`]
+    /// and dropped straight after, so a streaming front-end does not hold
+    /// every group in memory; the outcome's analysis then only carries
+    /// `total_saving_bytes`. Defaults to `true`.
+    pub fn with_collect_groups(mut self, collect: bool) -> Self {
+        self.collect_groups = collect;
+        self
+    }
+
+    /// Replace every walk setting at once.
+    pub fn with_walk_options(mut self, options: WalkOptions) -> Self {
+        self.walk = options;
+        self
+    }
+
+    /// The root directories this scanner walks.
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Run the scan, reporting progress to `observer`.
+    ///
+    /// Files are hashed in order of increasing size. Only files of equal size
+    /// can share content, so once every file of a given size has been hashed
+    /// its duplicate groups are final and are reported through
+    /// [`ScanObserver::group_found`] straight away, long before the whole scan
+    /// completes.
+    pub fn scan(&self, observer: &mut dyn ScanObserver) -> ScanOutcome {
+        let Walked { mut files, linked } = self.walk(observer);
+        observer.walk_finished(files.len());
+        files.sort_by_key(|(_, size)| *size);
+
+        let previous = self.load_checkpoint(observer);
+        let mut checkpoint = Checkpoint::new(&self.roots);
+        // A walk cut short found fewer files than an earlier, complete one.
+        checkpoint.files_discovered = if self.cancel.is_cancelled() {
+            files.len().max(previous.files_discovered)
+        } else {
+            files.len()
+        };
+        // Records not revisited yet are carried forward, so an interrupted
+        // run never loses hashes saved by an earlier one.
+        let mut previous = previous.into_lookup();
+        let mut last_save = Instant::now();
+
+        let mut analysis = DuplicateAnalysis::default();
+        let mut empty_files = Vec::new();
+        let mut linked_copies = Vec::new();
+
+        for bucket in files.chunk_by(|a, b| a.1 == b.1) {
+            // Empty files need no reading: their hash is known, and unless
+            // asked to they never form duplicate groups.
+            if bucket[0].1 == 0 && self.empty_files != EmptyFilePolicy::Include {
+                for (path, _) in bucket {
+                    if self.cancel.is_cancelled() {
+                        break;
+                    }
+                    observer.hashing_started(path);
+                    observer.hashing_finished(path, EMPTY_SHA256);
+                    if self.empty_files == EmptyFilePolicy::Report {
+                        empty_files.push(path.clone());
+                    }
+                }
+                empty_files.sort();
+                continue;
+            }
+
+            let mut map: HashMap<String, Vec<PathBuf>> = HashMap::new();
+            for (path, _) in bucket {
+                if self.cancel.is_cancelled() {
+                    break;
+                }
+                observer.hashing_started(path);
+
+                let stamp = match self.checkpoint.as_ref().map(|_| FileStamp::of(path)) {
+                    Some(Err(e)) => {
+                        observer.error(path, &e);
+                        continue;
+                    }
+                    Some(Ok(stamp)) => Some(stamp),
+                    None => None,
+                };
+                let reused = previous
+                    .remove(path)
+                    .filter(|record| Some(record.stamp) == stamp)
+                    .map(|record| record.hash);
+                let hashed = match reused {
+                    Some(hash) => Ok(hash),
+                    None => hash_file_with_progress(path, |bytes| observer.bytes_processed(bytes)),
+                };
+
+                match hashed {
+                    Ok(hash) => {
+                        observer.hashing_finished(path, &hash);
+                        if let Some(stamp) = stamp {
+                            checkpoint.files.push(FileRecord {
+                                path: path.clone(),
+                                stamp,
+                                hash: hash.clone(),
+                            });
+                        }
+                        map.entry(hash).or_default().push(path.clone());
+                    }
+                    Err(e) => observer.error(path, &e),
+                }
+
+                if last_save.elapsed() >= self.checkpoint_interval {
+                    self.save_checkpoint(&checkpoint, &previous, observer);
+                    last_save = Instant::now();
+                }
+            }
+
+            // Every file of this size has been hashed (or the scan was
+            // cancelled part-way through), so these groups are final.
+            let mut bucket_analysis = analyse_duplicates(map);
+            if !linked.is_empty() {
+                let copies;
+                (bucket_analysis, copies) = keep_linked_files(bucket_analysis, &linked);
+                linked_copies.extend(copies);
+            }
+            for group in &bucket_analysis.groups {
+                observer.group_found(group);
+            }
+            analysis.total_saving_bytes += bucket_analysis.total_saving_bytes;
+            if self.collect_groups {
+                analysis.groups.extend(bucket_analysis.groups);
+                analysis
+                    .removable_files
+                    .extend(bucket_analysis.removable_files);
+            }
+
+            if self.cancel.is_cancelled() {
+                break;
+            }
+        }
+        // After a complete scan, records never revisited are for files that
+        // are gone.
+        if !self.cancel.is_cancelled() {
+            previous.clear();
+        }
+        self.save_checkpoint(&checkpoint, &previous, observer);
+        analysis.sort_groups(GroupOrder::Path);
+        linked_copies.sort();
+
+        ScanOutcome {
+            analysis,
+            files_scanned: files.len(),
+            cancelled: self.cancel.is_cancelled(),
+            empty_files,
+            linked_copies,
+        }
+    }
+
+    /// Walk every root, returning each regular file with its size.
+    ///
+    /// Symlinks are skipped unless following them was requested, in which case
+    /// each file is returned once however many paths lead to it.
+    fn walk(&self, observer: &mut dyn ScanObserver) -> Walked {
+        let mut walked = Walked::default();
+        // Canonical path of every file found so far, when following symlinks.
+        let mut seen: HashMap<PathBuf, usize> = HashMap::new();
+        for root in &self.roots {
+            observer.walk_started(root);
+            let canonical_root = fs::canonicalize(root).ok();
+            for entry in self.walk.walker(root) {
+                if self.cancel.is_cancelled() {
+                    return walked;
+                }
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        let path = e.path().unwrap_or(root).to_path_buf();
+                        observer.error(&path, &io::Error::other(e));
+                        continue;
+                    }
+                };
+                // Without `follow_links` a symlink has its own file type, so
+                // symlinks are skipped here rather than hashed as their target
+                // (unless asked for). Device nodes, FIFOs and sockets are
+                // never regular files.
+                let meta = if entry.file_type().is_file() {
+                    entry.metadata().map_err(io::Error::other)
+                } else if self.walk.is_linked_file(&entry) {
+                    walked.linked.insert(entry.path().to_path_buf());
+                    fs::metadata(entry.path())
+                } else {
+                    continue;
+                };
+                let meta = match meta {
+                    Ok(meta) => meta,
+                    Err(e) => {
+                        observer.error(entry.path(), &e);
+                        continue;
+                    }
+                };
+                if self.walk.follow_symlinks {
+                    let canonical = match fs::canonicalize(entry.path()) {
+                        Ok(canonical) => canonical,
+                        Err(e) => {
+                            observer.error(entry.path(), &e);
+                            continue;
+                        }
+                    };
+                    let via_link = canonical_root.as_ref().is_none_or(|canonical_root| {
+                        entry
+                            .path()
+                            .strip_prefix(root)
+                            .map(|rel| canonical_root.join(rel) != canonical)
+                            .unwrap_or(true)
+                    });
+                    // The same file reached by another path: keep one path,
+                    // preferring one that does not go through a symlink.
+                    if let Some(&idx) = seen.get(&canonical) {
+                        let (existing, _): &mut (PathBuf, u64) = &mut walked.files[idx];
+                        if !via_link && walked.linked.remove(existing) {
+                            *existing = entry.into_path();
+                        }
+                        continue;
+                    }
+                    seen.insert(canonical, walked.files.len());
+                    if via_link {
+                        walked.linked.insert(entry.path().to_path_buf());
+                    }
+                }
+                observer.file_discovered(entry.path());
+                walked.files.push((entry.into_path(), meta.len()));
+            }
+        }
+        walked
+    }
+
+    /// Load the previous checkpoint, if any.
+    ///
+    /// A missing checkpoint simply means a fresh start; an unreadable one, or
+    /// one written for other roots, is reported and ignored.
+    fn load_checkpoint(&self, observer: &mut dyn ScanObserver) -> Checkpoint {
+        let Some(path) = self.checkpoint.as_deref() else {
+            return Checkpoint::default();
+        };
+        match Checkpoint::load(path) {
+            Ok(checkpoint) if checkpoint.roots != self.roots => {
+                let error = io::Error::other(format!(
+                    "checkpoint was written for {}, not {}; starting afresh",
+                    display_roots(&checkpoint.roots),
+                    display_roots(&self.roots)
+                ));
+                observer.error(path, &error);
+                Checkpoint::default()
+            }
+            Ok(checkpoint) => checkpoint,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Checkpoint::default(),
+            Err(e) => {
+                observer.error(path, &e);
+                Checkpoint::default()
+            }
+        }
+    }
+
+    /// Save `checkpoint` together with the `unvisited` records of the
+    /// previous one.
+    fn save_checkpoint(
+        &self,
+        checkpoint: &Checkpoint,
+        unvisited: &HashMap<PathBuf, FileRecord>,
+        observer: &mut dyn ScanObserver,
+    ) {
+        let Some(path) = self.checkpoint.as_deref() else {
+            return;
+        };
+        let result = if unvisited.is_empty() {
+            checkpoint.save(path)
+        } else {
+            let mut merged = checkpoint.clone();
+            merged.files.extend(unvisited.values().cloned());
+            merged.save(path)
+        };
+        if let Err(e) = result {
+            observer.error(path, &e);
+        }
+    }
+}
+
+/// Roots as a comma-separated list, for messages.
+fn display_roots(roots: &[PathBuf]) -> String {
+    roots
+        .iter()
+        .map(|root| format!("'{}'", root.display()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[derive(Default)]
+    struct Recorder {
+        discovered: usize,
+        hashed: usize,
+        bytes: u64,
+        groups: usize,
+        total: Option<usize>,
+    }
+
+    impl ScanObserver for Recorder {
+        fn file_discovered(&mut self, _path: &Path) {
+            self.discovered += 1;
+        }
+        fn walk_finished(&mut self, total_files: usize) {
+            self.total = Some(total_files);
+        }
+        fn hashing_finished(&mut self, _path: &Path, _hash: &str) {
+            self.hashed += 1;
+        }
+        fn bytes_processed(&mut self, bytes: u64) {
+            self.bytes += bytes;
+        }
+        fn group_found(&mut self, _group: &DuplicateGroup) {
+            self.groups += 1;
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_skipped_unless_followed() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("real")).unwrap();
+        fs::write(dir.path().join("real/a.txt"), b"same").unwrap();
+        fs::write(outside.path().join("b.txt"), b"same").unwrap();
+        symlink(dir.path().join("real/a.txt"), dir.path().join("link.txt")).unwrap();
+        symlink(dir.path().join("real"), dir.path().join("real-link")).unwrap();
+        symlink(outside.path(), dir.path().join("outside")).unwrap();
+        symlink(dir.path(), dir.path().join("real/loop")).unwrap();
+
+        let default = Scanner::new(dir.path()).scan(&mut NoopObserver);
+        assert_eq!(default.files_scanned, 1);
+        assert!(default.analysis.groups.is_empty());
+
+        #[derive(Default)]
+        struct Errors(usize);
+        impl ScanObserver for Errors {
+            fn error(&mut self, _path: &Path, _error: &io::Error) {
+                self.0 += 1;
+            }
+        }
+        let mut errors = Errors::default();
+        let followed = Scanner::new(dir.path())
+            .with_follow_symlinks(true)
+            .scan(&mut errors);
+        // `real/a.txt` once (however it is reached) plus `outside/b.txt`.
+        assert_eq!(followed.files_scanned, 2);
+        assert!(errors.0 >= 1, "the symlink loop is reported");
+        // `outside/b.txt` matches, but only through a symlink: never a dupe.
+        assert!(followed.analysis.groups.is_empty());
+        assert_eq!(
+            followed.linked_copies,
+            vec![(
+                dir.path().join("outside/b.txt"),
+                dir.path().join("real/a.txt")
+            )]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn any_type_reports_symlinked_copies() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), b"same").unwrap();
+        fs::write(outside.path().join("b.txt"), b"same").unwrap();
+        symlink(outside.path().join("b.txt"), dir.path().join("copy.txt")).unwrap();
+        symlink(dir.path().join("a.txt"), dir.path().join("self.txt")).unwrap();
+
+        let regular = Scanner::new(dir.path()).scan(&mut NoopObserver);
+        assert!(regular.linked_copies.is_empty());
+
+        let any = Scanner::new(dir.path())
+            .with_file_types(FileTypeFilter::Any)
+            .scan(&mut NoopObserver);
+        assert_eq!(any.files_scanned, 3);
+        assert!(any.analysis.groups.is_empty());
+        // `self.txt` points at `a.txt` itself, so only `copy.txt` is a copy.
+        assert_eq!(
+            any.linked_copies,
+            vec![(dir.path().join("copy.txt"), dir.path().join("a.txt"))]
+        );
+    }
+
+    #[test]
+    fn empty_files_follow_the_policy() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.lock"), b"").unwrap();
+        fs::write(dir.path().join("b.lock"), b"").unwrap();
+
+        let ignored = Scanner::new(dir.path()).scan(&mut NoopObserver);
+        assert!(ignored.analysis.groups.is_empty());
+        assert!(ignored.empty_files.is_empty());
+        assert_eq!(ignored.files_scanned, 2);
+
+        let reported = Scanner::new(dir.path())
+            .with_empty_files(EmptyFilePolicy::Report)
+            .scan(&mut NoopObserver);
+        assert!(reported.analysis.groups.is_empty());
+        assert_eq!(
+            reported.empty_files,
+            [dir.path().join("a.lock"), dir.path().join("b.lock")]
+        );
+
+        let included = Scanner::new(dir.path())
+            .with_empty_files(EmptyFilePolicy::Include)
+            .scan(&mut NoopObserver);
+        assert_eq!(included.analysis.groups.len(), 1);
+        assert_eq!(included.analysis.groups[0].hash, EMPTY_SHA256);
+    }
+
+    #[test]
+    fn scan_reports_progress_to_observer() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), b"same").unwrap();
+        fs::write(dir.path().join("b.txt"), b"same").unwrap();
+        fs::write(dir.path().join("c.txt"), b"other").unwrap();
+
+        let mut recorder = Recorder::default();
+        let outcome = Scanner::new(dir.path()).scan(&mut recorder);
+
+        assert_eq!(outcome.files_scanned, 3);
+        assert_eq!(outcome.analysis.groups.len(), 1);
+        assert_eq!(recorder.discovered, 3);
+        assert_eq!(recorder.total, Some(3));
+        assert_eq!(recorder.hashed, 3);
+        assert_eq!(recorder.bytes, 13);
+        assert_eq!(recorder.groups, 1);
+        assert!(!outcome.cancelled);
+
+        let mut recorder = Recorder::default();
+        let streamed = Scanner::new(dir.path())
+            .with_collect_groups(false)
+            .scan(&mut recorder);
+        assert_eq!(recorder.groups, 1);
+        assert!(streamed.analysis.groups.is_empty());
+        assert!(streamed.analysis.removable_files.is_empty());
+        assert_eq!(streamed.analysis.total_saving_bytes, 4);
+    }
+
+    /// Records the order of hashing and group events.
+    #[derive(Default)]
+    struct EventLog(Vec<String>);
+
+    impl ScanObserver for EventLog {
+        fn hashing_started(&mut self, path: &Path) {
+            let name = path.file_name().unwrap().to_string_lossy();
+            self.0.push(format!("hash {name}"));
+        }
+        fn group_found(&mut self, group: &DuplicateGroup) {
+            self.0.push(format!("group {}", group.size));
+        }
+    }
+
+    #[test]
+    fn scan_reports_groups_as_soon_as_their_size_is_done() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("small-1"), b"ab").unwrap();
+        fs::write(dir.path().join("small-2"), b"ab").unwrap();
+        fs::write(dir.path().join("large-1"), b"abcd").unwrap();
+        fs::write(dir.path().join("large-2"), b"abcd").unwrap();
+
+        let mut log = EventLog::default();
+        let outcome = Scanner::new(dir.path()).scan(&mut log);
+
+        assert_eq!(outcome.analysis.groups.len(), 2);
+        assert_eq!(outcome.analysis.total_saving_bytes, 6);
+        let small_group = log.0.iter().position(|e| e == "group 2").unwrap();
+        let first_large = log
+            .0
+            .iter()
+            .position(|e| e.starts_with("hash large"))
+            .unwrap();
+        assert!(
+            small_group < first_large,
+            "small group should be reported before large files are hashed: {:?}",
+            log.0
+        );
+    }
+
+    struct CancelAfterFirstHash(CancellationToken);
+
+    impl ScanObserver for CancelAfterFirstHash {
+        fn hashing_finished(&mut self, _path: &Path, _hash: &str) {
+            self.0.cancel();
+        }
+    }
+
+    #[test]
+    fn scan_stops_after_current_file_when_cancelled() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), b"same").unwrap();
+        fs::write(dir.path().join("b.txt"), b"same").unwrap();
+
+        let token = CancellationToken::new();
+        let mut observer = CancelAfterFirstHash(token.clone());
+        let outcome = Scanner::new(dir.path())
+            .with_cancellation(token)
+            .scan(&mut observer);
+
+        assert!(outcome.cancelled);
+        assert_eq!(outcome.files_scanned, 2);
+        assert!(outcome.analysis.groups.is_empty());
+    }
+
+    #[test]
+    fn scan_resumes_from_checkpoint_and_rehashes_changed_files() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let unchanged = root.join("a.txt");
+        let changed = root.join("b.txt");
+        fs::write(&unchanged, b"aaa").unwrap();
+        fs::write(&changed, b"bbb").unwrap();
+        let checkpoint_path = dir.path().join("checkpoint.json");
+
+        // Pretend a previous run hashed both files. The recorded hash for the
+        // unchanged file is bogus, so reusing it is observable.
+        let mut previous = Checkpoint::new(std::slice::from_ref(&root));
+        previous.files.push(FileRecord {
+            path: unchanged.clone(),
+            stamp: FileStamp::of(&unchanged).unwrap(),
+            hash: "from-checkpoint".to_string(),
+        });
+        previous.files.push(FileRecord {
+            path: changed.clone(),
+            stamp: FileStamp {
+                size: 999,
+                mtime_ns: None,
+            },
+            hash: "stale".to_string(),
+        });
+        previous.save(&checkpoint_path).unwrap();
+
+        let mut recorder = Recorder::default();
+        let outcome = Scanner::new(&root)
+            .with_checkpoint(&checkpoint_path)
+            .scan(&mut recorder);
+
+        assert_eq!(outcome.files_scanned, 2);
+        assert_eq!(recorder.bytes, 3, "only the changed file should be read");
+
+        let saved = Checkpoint::load(&checkpoint_path).unwrap().into_lookup();
+        assert_eq!(saved[&unchanged].hash, "from-checkpoint");
+        assert_eq!(saved[&changed].hash, crate::hash_file(&changed).unwrap());
+    }
+
+    #[test]
+    fn cancelled_resume_keeps_earlier_records() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let a = root.join("a.txt");
+        let b = root.join("b.txt");
+        fs::write(&a, b"same").unwrap();
+        fs::write(&b, b"same").unwrap();
+        let checkpoint_path = dir.path().join("checkpoint.json");
+
+        Scanner::new(&root)
+            .with_checkpoint(&checkpoint_path)
+            .scan(&mut NoopObserver);
+
+        // Cancelled during the walk: nothing is revisited.
+        let token = CancellationToken::new();
+        token.cancel();
+        let outcome = Scanner::new(&root)
+            .with_checkpoint(&checkpoint_path)
+            .with_cancellation(token)
+            .scan(&mut NoopObserver);
+        assert!(outcome.cancelled);
+        let saved = Checkpoint::load(&checkpoint_path).unwrap();
+        assert_eq!(saved.files_discovered, 2);
+        assert_eq!(saved.into_lookup().len(), 2);
+
+        // Cancelled after one file: the other is carried forward.
+        let token = CancellationToken::new();
+        Scanner::new(&root)
+            .with_checkpoint(&checkpoint_path)
+            .with_cancellation(token.clone())
+            .scan(&mut CancelAfterFirstHash(token));
+        let saved = Checkpoint::load(&checkpoint_path).unwrap().into_lookup();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[&a].hash, crate::hash_file(&a).unwrap());
+        assert_eq!(saved[&b].hash, crate::hash_file(&b).unwrap());
+    }
+
+    #[test]
+    fn checkpoints_for_other_roots_are_not_reused() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, b"aaa").unwrap();
+        let checkpoint_path = dir.path().join("checkpoint.json");
+
+        let mut other = Checkpoint::new(&[dir.path().join("elsewhere")]);
+        other.files.push(FileRecord {
+            path: file.clone(),
+            stamp: FileStamp::of(&file).unwrap(),
+            hash: "from-checkpoint".to_string(),
+        });
+        other.save(&checkpoint_path).unwrap();
+
+        #[derive(Default)]
+        struct Errors(Vec<String>);
+        impl ScanObserver for Errors {
+            fn error(&mut self, _path: &Path, error: &io::Error) {
+                self.0.push(error.to_string());
+            }
+        }
+        let mut errors = Errors::default();
+        Scanner::new(dir.path())
+            .with_checkpoint(&checkpoint_path)
+            .scan(&mut errors);
+
+        assert!(errors.0[0].contains("starting afresh"), "{:?}", errors.0);
+        let saved = Checkpoint::load(&checkpoint_path).unwrap().into_lookup();
+        assert_eq!(saved[&file].hash, crate::hash_file(&file).unwrap());
+    }
+}
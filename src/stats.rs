@@ -0,0 +1,245 @@
+//! Waste statistics derived from a [`DuplicateAnalysis`].
+//!
+//! These answer "where is the duplication?" before anything is cleaned up:
+//! reclaimable bytes per top-level directory, per file extension and per size
+//! band, plus the directory pairs that share the most duplicated content.
+
+use crate::DuplicateAnalysis;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+const KB: u64 = 1024;
+const MB: u64 = KB * 1024;
+const GB: u64 = MB * 1024;
+
+/// Size bands used by [`WasteStats::by_size_band`], as `(label, upper bound)`.
+const SIZE_BANDS: [(&str, u64); 5] = [
+    ("< 1 KB", KB),
+    ("1 KB - 1 MB", MB),
+    ("1 MB - 100 MB", 100 * MB),
+    ("100 MB - 1 GB", GB),
+    (">= 1 GB", u64::MAX),
+];
+
+/// Reclaimable space attributed to one key (a directory, extension or band).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasteEntry {
+    /// What the bytes are attributed to.
+    pub key: String,
+    /// Number of removable files.
+    pub files: usize,
+    /// Bytes freed by removing them.
+    pub bytes: u64,
+}
+
+/// Two directories that hold copies of the same content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryPair {
+    /// The lexicographically smaller directory.
+    pub first: PathBuf,
+    /// The other directory.
+    pub second: PathBuf,
+    /// Number of duplicate groups with a copy in both directories.
+    pub groups: usize,
+    /// Size of the content the two directories have in common.
+    pub bytes: u64,
+}
+
+/// Summary of where duplicated bytes live.
+#[derive(Debug, Clone, Default)]
+pub struct WasteStats {
+    /// Reclaimable bytes per top-level directory under the scan roots, largest
+    /// first. Files directly inside a root are reported as `.`.
+    pub by_directory: Vec<WasteEntry>,
+    /// Reclaimable bytes per lower-cased file extension, largest first. Files
+    /// without an extension are reported as `(none)`.
+    pub by_extension: Vec<WasteEntry>,
+    /// Reclaimable bytes per file size band, smallest band first. Empty bands
+    /// are omitted.
+    pub by_size_band: Vec<WasteEntry>,
+    /// Directory pairs sharing the most duplicated content, largest first.
+    pub shared_directories: Vec<DirectoryPair>,
+}
+
+impl WasteStats {
+    /// Compute statistics for `analysis`. `roots` are the scanned directories,
+    /// used to find each file's top-level directory.
+    pub fn from_analysis(analysis: &DuplicateAnalysis, roots: &[PathBuf]) -> Self {
+        let mut by_directory: HashMap<String, (usize, u64)> = HashMap::new();
+        let mut by_extension: HashMap<String, (usize, u64)> = HashMap::new();
+        let mut by_band = [(0usize, 0u64); SIZE_BANDS.len()];
+        let mut pairs: HashMap<(PathBuf, PathBuf), (usize, u64)> = HashMap::new();
+
+        for group in &analysis.groups {
+            for dupe in &group.dupes {
+                add(
+                    &mut by_directory,
+                    top_level_directory(dupe, roots),
+                    group.size,
+                );
+                add(&mut by_extension, extension(dupe), group.size);
+
+                let band = SIZE_BANDS
+                    .iter()
+                    .position(|(_, upper)| group.size < *upper)
+                    .unwrap_or(SIZE_BANDS.len() - 1);
+                by_band[band].0 += 1;
+                by_band[band].1 += group.size;
+            }
+
+            let dirs: BTreeSet<&Path> = std::iter::once(&group.keep)
+                .chain(&group.dupes)
+                .filter_map(|p| p.parent())
+                .collect();
+            let dirs: Vec<&Path> = dirs.into_iter().collect();
+            for (i, first) in dirs.iter().enumerate() {
+                for second in &dirs[i + 1..] {
+                    let entry = pairs
+                        .entry((first.to_path_buf(), second.to_path_buf()))
+                        .or_default();
+                    entry.0 += 1;
+                    entry.1 += group.size;
+                }
+            }
+        }
+
+        let by_size_band = SIZE_BANDS
+            .iter()
+            .zip(by_band)
+            .filter(|(_, (files, _))| *files > 0)
+            .map(|((label, _), (files, bytes))| WasteEntry {
+                key: label.to_string(),
+                files,
+                bytes,
+            })
+            .collect();
+
+        let mut shared_directories: Vec<DirectoryPair> = pairs
+            .into_iter()
+            .map(|((first, second), (groups, bytes))| DirectoryPair {
+                first,
+                second,
+                groups,
+                bytes,
+            })
+            .collect();
+        shared_directories.sort_by(|a, b| {
+            b.bytes
+                .cmp(&a.bytes)
+                .then_with(|| a.first.cmp(&b.first))
+                .then_with(|| a.second.cmp(&b.second))
+        });
+
+        Self {
+            by_directory: into_sorted_entries(by_directory),
+            by_extension: into_sorted_entries(by_extension),
+            by_size_band,
+            shared_directories,
+        }
+    }
+}
+
+fn add(map: &mut HashMap<String, (usize, u64)>, key: String, bytes: u64) {
+    let entry = map.entry(key).or_default();
+    entry.0 += 1;
+    entry.1 += bytes;
+}
+
+fn into_sorted_entries(map: HashMap<String, (usize, u64)>) -> Vec<WasteEntry> {
+    let mut entries: Vec<WasteEntry> = map
+        .into_iter()
+        .map(|(key, (files, bytes))| WasteEntry { key, files, bytes })
+        .collect();
+    entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+    entries
+}
+
+/// The first path component below whichever root contains `path`.
+fn top_level_directory(path: &Path, roots: &[PathBuf]) -> String {
+    let relative = roots.iter().find_map(|root| path.strip_prefix(root).ok());
+    match relative {
+        Some(rel) => {
+            let mut components = rel.components();
+            match (components.next(), components.next()) {
+                (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+                _ => ".".to_string(),
+            }
+        }
+        None => path
+            .parent()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "(none)".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicateGroup;
+
+    fn group(size: u64, keep: &str, dupes: &[&str]) -> DuplicateGroup {
+        DuplicateGroup {
+            hash: keep.to_string(),
+            size,
+            keep: keep.into(),
+            dupes: dupes.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn stats_break_down_reclaimable_bytes() {
+        let analysis = DuplicateAnalysis {
+            groups: vec![
+                group(
+                    2 * MB,
+                    "/r/photos/a.JPG",
+                    &["/r/backup/a.jpg", "/r/backup/old/a.jpg"],
+                ),
+                group(10, "/r/photos/notes", &["/r/top-level-file"]),
+            ],
+            ..DuplicateAnalysis::default()
+        };
+
+        let stats = WasteStats::from_analysis(&analysis, &[PathBuf::from("/r")]);
+
+        assert_eq!(
+            stats.by_directory,
+            vec![
+                WasteEntry {
+                    key: "backup".into(),
+                    files: 2,
+                    bytes: 4 * MB
+                },
+                WasteEntry {
+                    key: ".".into(),
+                    files: 1,
+                    bytes: 10
+                },
+            ]
+        );
+        assert_eq!(stats.by_extension[0].key, "jpg");
+        assert_eq!(stats.by_extension[1].key, "(none)");
+        assert_eq!(
+            stats
+                .by_size_band
+                .iter()
+                .map(|e| e.key.as_str())
+                .collect::<Vec<_>>(),
+            ["< 1 KB", "1 MB - 100 MB"]
+        );
+
+        let top = &stats.shared_directories[0];
+        assert_eq!(top.first, PathBuf::from("/r/backup"));
+        assert_eq!(top.second, PathBuf::from("/r/backup/old"));
+        assert_eq!(top.bytes, 2 * MB);
+        assert_eq!(stats.shared_directories.len(), 4);
+    }
+}
@@ -0,0 +1,623 @@
+//! Full-screen review of duplicate groups (`--tui`).
+//!
+//! Groups are listed by waste on the left, and the files of the selected group
+//! on the right, each marked keep or delete. Nothing is deleted while
+//! reviewing: applying returns the marks as a plan of [`DuplicateGroup`]s for
+//! the caller to delete, and quitting returns no plan at all.
+
+use ddupe::{DuplicateGroup, format_bytes, report::format_timestamp, safety::ProtectedPaths};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+};
+use std::{fs, io, path::PathBuf};
+
+const HELP: &str = "↑↓ move  ←→/Tab switch  space keep/delete  o keep only this  K keep all  u undo  / search  a apply  q quit";
+
+/// What a file in a group will become.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mark {
+    Keep,
+    Delete,
+}
+
+/// Which list the cursor keys move in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    Groups,
+    Files,
+}
+
+/// How a review ended.
+#[derive(Debug, PartialEq, Eq)]
+enum Exit {
+    Apply,
+    Quit,
+}
+
+/// Review state: the groups, every file's mark and the cursor.
+struct Review<'a> {
+    /// Groups by waste, largest first.
+    groups: Vec<&'a DuplicateGroup>,
+    /// Per group, the mark of each file: the default keep first, then dupes.
+    marks: Vec<Vec<Mark>>,
+    /// Per group, which files are protected and can only be kept.
+    protected: Vec<Vec<bool>>,
+    /// Indexes into `groups` that match the filter.
+    visible: Vec<usize>,
+    group_list: ListState,
+    file_list: ListState,
+    focus: Focus,
+    filter: String,
+    searching: bool,
+    confirming: bool,
+    /// Earlier marks of a group, most recent change last.
+    undo: Vec<(usize, Vec<Mark>)>,
+    status: String,
+}
+
+/// The files of `group`, default keep first.
+fn candidates(group: &DuplicateGroup) -> impl Iterator<Item = &PathBuf> {
+    std::iter::once(&group.keep).chain(&group.dupes)
+}
+
+impl<'a> Review<'a> {
+    fn new(groups: &'a [DuplicateGroup], protect: &ProtectedPaths) -> Self {
+        let mut groups: Vec<&DuplicateGroup> = groups.iter().collect();
+        groups.sort_by(|a, b| {
+            b.reclaimable_bytes()
+                .cmp(&a.reclaimable_bytes())
+                .then_with(|| a.keep.cmp(&b.keep))
+        });
+        let protected: Vec<Vec<bool>> = groups
+            .iter()
+            .map(|g| candidates(g).map(|p| protect.is_protected(p)).collect())
+            .collect();
+        let marks = protected
+            .iter()
+            .map(|flags| {
+                flags
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &protected)| {
+                        if i == 0 || protected {
+                            Mark::Keep
+                        } else {
+                            Mark::Delete
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut review = Self {
+            visible: (0..groups.len()).collect(),
+            groups,
+            marks,
+            protected,
+            group_list: ListState::default(),
+            file_list: ListState::default(),
+            focus: Focus::Groups,
+            filter: String::new(),
+            searching: false,
+            confirming: false,
+            undo: Vec::new(),
+            status: String::new(),
+        };
+        review.select_group(0);
+        review
+    }
+
+    /// Index into `groups` of the group under the cursor.
+    fn current_group(&self) -> Option<usize> {
+        self.group_list
+            .selected()
+            .and_then(|i| self.visible.get(i).copied())
+    }
+
+    /// Group and file index of the file under the cursor.
+    fn current_file(&self) -> Option<(usize, usize)> {
+        Some((self.current_group()?, self.file_list.selected()?))
+    }
+
+    fn select_group(&mut self, index: usize) {
+        if self.visible.is_empty() {
+            self.group_list.select(None);
+            self.file_list.select(None);
+        } else {
+            self.group_list
+                .select(Some(index.min(self.visible.len() - 1)));
+            self.file_list.select(Some(0));
+        }
+    }
+
+    /// Move the cursor of the focused list by `delta` rows.
+    fn move_cursor(&mut self, delta: isize) {
+        match self.focus {
+            Focus::Groups => {
+                let current = self.group_list.selected().unwrap_or(0);
+                self.select_group(current.saturating_add_signed(delta));
+            }
+            Focus::Files => {
+                if let (Some(group), Some(file)) = (self.current_group(), self.file_list.selected())
+                {
+                    let last = self.marks[group].len() - 1;
+                    self.file_list
+                        .select(Some(file.saturating_add_signed(delta).min(last)));
+                }
+            }
+        }
+    }
+
+    /// Change the marks of `group`, remembering the old ones for undo.
+    fn update_marks(&mut self, group: usize, marks: Vec<Mark>) {
+        if marks != self.marks[group] {
+            let previous = std::mem::replace(&mut self.marks[group], marks);
+            self.undo.push((group, previous));
+        }
+    }
+
+    /// Flip the file under the cursor between keep and delete.
+    fn toggle(&mut self) {
+        let Some((group, file)) = self.current_file() else {
+            return;
+        };
+        let mut marks = self.marks[group].clone();
+        match marks[file] {
+            Mark::Delete => marks[file] = Mark::Keep,
+            Mark::Keep if self.protected[group][file] => {
+                self.status = "That file is protected and is always kept.".to_string();
+                return;
+            }
+            Mark::Keep if marks.iter().filter(|&&m| m == Mark::Keep).count() == 1 => {
+                self.status = "Every group keeps at least one file.".to_string();
+                return;
+            }
+            Mark::Keep => marks[file] = Mark::Delete,
+        }
+        self.update_marks(group, marks);
+    }
+
+    /// Keep only the file under the cursor (and any protected files).
+    fn keep_only(&mut self) {
+        let Some((group, file)) = self.current_file() else {
+            return;
+        };
+        let marks = self.protected[group]
+            .iter()
+            .enumerate()
+            .map(|(i, &protected)| {
+                if i == file || protected {
+                    Mark::Keep
+                } else {
+                    Mark::Delete
+                }
+            })
+            .collect();
+        self.update_marks(group, marks);
+    }
+
+    /// Keep every file of the group under the cursor.
+    fn keep_all(&mut self) {
+        if let Some(group) = self.current_group() {
+            let marks = vec![Mark::Keep; self.marks[group].len()];
+            self.update_marks(group, marks);
+        }
+    }
+
+    /// Restore the marks from before the last change.
+    fn undo(&mut self) {
+        let Some((group, marks)) = self.undo.pop() else {
+            self.status = "Nothing to undo.".to_string();
+            return;
+        };
+        self.marks[group] = marks;
+        if let Some(row) = self.visible.iter().position(|&g| g == group) {
+            self.select_group(row);
+        }
+    }
+
+    /// Show only groups with a path containing `filter`, ignoring case.
+    fn set_filter(&mut self, filter: String) {
+        let needle = filter.to_lowercase();
+        self.visible = (0..self.groups.len())
+            .filter(|&g| {
+                candidates(self.groups[g])
+                    .any(|p| p.to_string_lossy().to_lowercase().contains(&needle))
+            })
+            .collect();
+        self.filter = filter;
+        self.select_group(0);
+    }
+
+    /// The marks as a deletion plan: one group per reviewed group with
+    /// something to delete, keeping its first kept file.
+    fn plan(&self) -> Vec<DuplicateGroup> {
+        self.groups
+            .iter()
+            .zip(&self.marks)
+            .filter_map(|(group, marks)| {
+                let files: Vec<(&PathBuf, Mark)> =
+                    candidates(group).zip(marks.iter().copied()).collect();
+                let keep = files.iter().find(|(_, m)| *m == Mark::Keep)?.0.clone();
+                let dupes: Vec<PathBuf> = files
+                    .iter()
+                    .filter(|(_, m)| *m == Mark::Delete)
+                    .map(|(p, _)| (*p).clone())
+                    .collect();
+                (!dupes.is_empty()).then(|| DuplicateGroup {
+                    hash: group.hash.clone(),
+                    size: group.size,
+                    keep,
+                    dupes,
+                })
+            })
+            .collect()
+    }
+
+    /// Number of files marked for deletion and the bytes they hold.
+    fn planned(&self) -> (usize, u64) {
+        self.groups
+            .iter()
+            .zip(&self.marks)
+            .fold((0, 0), |(count, bytes), (group, marks)| {
+                let deleted = marks.iter().filter(|&&m| m == Mark::Delete).count();
+                (count + deleted, bytes + group.size * deleted as u64)
+            })
+    }
+
+    /// React to one key press. Returns how the review ended, if it did.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Exit> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Exit::Quit);
+        }
+        self.status.clear();
+
+        if self.confirming {
+            self.confirming = false;
+            if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+                return Some(Exit::Apply);
+            }
+            self.status = "Not applied.".to_string();
+            return None;
+        }
+
+        if self.searching {
+            match key.code {
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.set_filter(String::new());
+                }
+                KeyCode::Backspace => {
+                    let mut filter = self.filter.clone();
+                    filter.pop();
+                    self.set_filter(filter);
+                }
+                KeyCode::Char(c) => {
+                    let mut filter = self.filter.clone();
+                    filter.push(c);
+                    self.set_filter(filter);
+                }
+                _ => {}
+            }
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return Some(Exit::Quit),
+            KeyCode::Esc if !self.filter.is_empty() => self.set_filter(String::new()),
+            KeyCode::Esc => return Some(Exit::Quit),
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(1),
+            KeyCode::PageUp => self.move_cursor(-10),
+            KeyCode::PageDown => self.move_cursor(10),
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Groups => Focus::Files,
+                    Focus::Files => Focus::Groups,
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.focus = Focus::Groups,
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.focus = Focus::Files,
+            KeyCode::Char(' ') => {
+                self.focus = Focus::Files;
+                self.toggle();
+            }
+            KeyCode::Char('o') => self.keep_only(),
+            KeyCode::Char('K') => self.keep_all(),
+            KeyCode::Char('u') => self.undo(),
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Char('a') => self.confirming = true,
+            _ => {}
+        }
+        None
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, details, footer] = Layout::vertical([
+            Constraint::Min(5),
+            Constraint::Length(6),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+        let focused = |focus| {
+            if self.focus == focus {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            }
+        };
+
+        let groups: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&g| {
+                let group = self.groups[g];
+                let deleted = self.marks[g].iter().filter(|&&m| m == Mark::Delete).count();
+                let name = group
+                    .keep
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| group.keep.display().to_string());
+                ListItem::new(format!(
+                    "{:>10}  {}/{}  {}",
+                    format_bytes(group.size * deleted as u64),
+                    deleted,
+                    self.marks[g].len(),
+                    name
+                ))
+            })
+            .collect();
+        let title = if self.filter.is_empty() {
+            format!(" Groups ({}) ", self.groups.len())
+        } else {
+            format!(
+                " Groups ({}/{}) matching '{}' ",
+                self.visible.len(),
+                self.groups.len(),
+                self.filter
+            )
+        };
+        frame.render_stateful_widget(
+            List::new(groups)
+                .block(
+                    Block::bordered()
+                        .title(title)
+                        .border_style(focused(Focus::Groups)),
+                )
+                .highlight_style(highlight),
+            left,
+            &mut self.group_list,
+        );
+
+        let files: Vec<ListItem> = self
+            .current_group()
+            .map(|g| {
+                candidates(self.groups[g])
+                    .zip(&self.marks[g])
+                    .zip(&self.protected[g])
+                    .map(|((path, mark), &protected)| {
+                        let (label, color) = match mark {
+                            Mark::Keep if protected => ("[PROT]", Color::Blue),
+                            Mark::Keep => ("[KEEP]", Color::Green),
+                            Mark::Delete => ("[DEL ]", Color::Red),
+                        };
+                        ListItem::new(Line::from(vec![
+                            Span::styled(label, Style::default().fg(color)),
+                            Span::raw(format!(" {}", path.display())),
+                        ]))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        frame.render_stateful_widget(
+            List::new(files)
+                .block(
+                    Block::bordered()
+                        .title(" Files ")
+                        .border_style(focused(Focus::Files)),
+                )
+                .highlight_style(highlight),
+            right,
+            &mut self.file_list,
+        );
+
+        frame.render_widget(
+            Paragraph::new(self.details()).block(Block::bordered().title(" Details ")),
+            details,
+        );
+
+        let (count, bytes) = self.planned();
+        let footer_text = if self.confirming {
+            format!(
+                "Delete {count} file(s), freeing {}? [y/N]",
+                format_bytes(bytes)
+            )
+        } else if self.searching {
+            format!("/{}", self.filter)
+        } else if !self.status.is_empty() {
+            self.status.clone()
+        } else {
+            format!("{count} file(s) marked, {}  |  {HELP}", format_bytes(bytes))
+        };
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+
+    /// Metadata of the file under the cursor.
+    fn details(&self) -> Vec<Line<'static>> {
+        let Some((group, file)) = self.current_file() else {
+            return vec![Line::from("No groups match.")];
+        };
+        let group = self.groups[group];
+        let Some(path) = candidates(group).nth(file) else {
+            return Vec::new();
+        };
+        let mut lines = vec![
+            Line::from(format!("Path:     {}", path.display())),
+            Line::from(format!("SHA-256:  {}", group.hash)),
+        ];
+        match fs::metadata(path) {
+            Ok(metadata) => {
+                lines.push(Line::from(format!(
+                    "Size:     {} ({} bytes)",
+                    format_bytes(metadata.len()),
+                    metadata.len()
+                )));
+                let modified = metadata
+                    .modified()
+                    .map(format_timestamp)
+                    .unwrap_or_else(|_| "unknown".to_string());
+                let access = if metadata.permissions().readonly() {
+                    "read-only"
+                } else {
+                    "writable"
+                };
+                lines.push(Line::from(format!("Modified: {modified}  ({access})")));
+            }
+            Err(e) => lines.push(Line::from(format!("Cannot read metadata: {e}"))),
+        }
+        lines
+    }
+}
+
+/// Let the user review `groups` full-screen.
+///
+/// Returns the plan to delete, or `None` if the user quit without applying.
+/// Protected files are always kept and every group keeps at least one file.
+pub fn review(
+    groups: &[DuplicateGroup],
+    protect: &ProtectedPaths,
+) -> io::Result<Option<Vec<DuplicateGroup>>> {
+    let mut terminal = ratatui::try_init()?;
+    let result = run(&mut terminal, Review::new(groups, protect));
+    ratatui::restore();
+    result
+}
+
+fn run(
+    terminal: &mut DefaultTerminal,
+    mut review: Review,
+) -> io::Result<Option<Vec<DuplicateGroup>>> {
+    loop {
+        terminal.draw(|frame| review.draw(frame))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match review.handle_key(key) {
+                Some(Exit::Apply) => return Ok(Some(review.plan())),
+                Some(Exit::Quit) => return Ok(None),
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{Terminal, backend::TestBackend};
+
+    fn group(size: u64, paths: &[&str]) -> DuplicateGroup {
+        DuplicateGroup {
+            hash: paths[0].to_string(),
+            size,
+            keep: paths[0].into(),
+            dupes: paths[1..].iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    fn press(review: &mut Review, keys: &str) -> Option<Exit> {
+        keys.chars()
+            .map(|c| review.handle_key(KeyEvent::from(KeyCode::Char(c))))
+            .last()
+            .flatten()
+    }
+
+    #[test]
+    fn marks_become_a_plan_that_keeps_one_file_per_group() {
+        let groups = [
+            group(1, &["/a/small", "/b/small"]),
+            group(100, &["/a/big", "/b/big", "/archive/big"]),
+        ];
+        let protect = ProtectedPaths::new(&["/archive".to_string()]).unwrap();
+        let mut review = Review::new(&groups, &protect);
+
+        // Largest waste first; the protected copy starts out kept.
+        assert_eq!(review.groups[0].keep, PathBuf::from("/a/big"));
+        assert_eq!(review.planned(), (2, 101));
+
+        // Keep /b/big instead of /a/big, then try to delete the protected copy.
+        press(&mut review, "ljoj ");
+        assert_eq!(review.marks[0], [Mark::Delete, Mark::Keep, Mark::Keep]);
+        assert!(review.status.contains("protected"));
+
+        // The last kept file of a group cannot be marked for deletion.
+        press(&mut review, "hjl ");
+        assert_eq!(review.marks[1], [Mark::Keep, Mark::Delete]);
+        assert!(review.status.contains("at least one"));
+
+        // Keep everything in the small group, then change our mind.
+        press(&mut review, "K");
+        assert_eq!(review.planned(), (1, 100));
+        press(&mut review, "u");
+        assert_eq!(review.planned(), (2, 101));
+
+        assert_eq!(press(&mut review, "a"), None);
+        assert_eq!(press(&mut review, "y"), Some(Exit::Apply));
+        let plan = review.plan();
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan[0].keep, PathBuf::from("/b/big"));
+        assert_eq!(plan[0].dupes, [PathBuf::from("/a/big")]);
+    }
+
+    #[test]
+    fn search_filters_the_group_list() {
+        let groups = [
+            group(1, &["/photos/a.jpg", "/photos/b.jpg"]),
+            group(2, &["/music/a.mp3", "/music/b.mp3"]),
+        ];
+        let mut review = Review::new(&groups, &ProtectedPaths::default());
+
+        press(&mut review, "/PHOTO");
+        assert_eq!(review.visible.len(), 1);
+        assert_eq!(
+            review.groups[review.current_group().unwrap()].keep,
+            PathBuf::from("/photos/a.jpg")
+        );
+
+        review.handle_key(KeyEvent::from(KeyCode::Enter));
+        review.handle_key(KeyEvent::from(KeyCode::Esc));
+        assert_eq!(review.visible.len(), 2);
+        assert_eq!(press(&mut review, "q"), Some(Exit::Quit));
+    }
+
+    #[test]
+    fn draws_groups_files_and_totals() {
+        let groups = [group(1024, &["/a/x", "/b/x"])];
+        let mut review = Review::new(&groups, &ProtectedPaths::default());
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| review.draw(frame)).unwrap();
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Groups (1)"));
+        assert!(screen.contains("[KEEP] /a/x"));
+        assert!(screen.contains("[DEL ] /b/x"));
+        assert!(screen.contains("1 file(s) marked"));
+    }
+}
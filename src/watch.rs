@@ -0,0 +1,277 @@
+//! Index of existing content for watching directories.
+//!
+//! [`WatchIndex`] remembers every file under the watched roots by size and
+//! hashes lazily, like a scan does: a new file is hashed, and existing files
+//! with it, only when they have the same size. Feeding it each file as it is
+//! written tells whether that file duplicates content that was already there.
+//! Watching the filesystem itself is up to the front-end.
+
+use crate::{WalkOptions, checkpoint::FileStamp, collect_files_with, hash_file};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A new file whose content already exists under the watched roots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchMatch {
+    /// The file that was just added.
+    pub path: PathBuf,
+    /// An existing file with the same content.
+    pub original: PathBuf,
+    /// SHA-256 of the shared content.
+    pub hash: String,
+    /// Size of each copy in bytes.
+    pub size: u64,
+}
+
+/// Sizes (and, once computed, hashes) of every file under some roots.
+#[derive(Debug, Default)]
+pub struct WatchIndex {
+    roots: Vec<PathBuf>,
+    options: WalkOptions,
+    by_size: HashMap<u64, Vec<PathBuf>>,
+    stamps: HashMap<PathBuf, FileStamp>,
+    hashes: HashMap<PathBuf, String>,
+}
+
+impl WatchIndex {
+    /// Index every file under `roots`, walked according to `options`. Nothing
+    /// is hashed yet.
+    pub fn build(roots: &[PathBuf], options: &WalkOptions) -> Self {
+        let mut index = Self {
+            roots: roots.to_vec(),
+            options: options.clone(),
+            ..Self::default()
+        };
+        for root in roots {
+            for path in collect_files_with(root, options) {
+                if let Ok(stamp) = FileStamp::of(&path) {
+                    index.insert(path, stamp);
+                }
+            }
+        }
+        index
+    }
+
+    /// Number of files in the index.
+    pub fn len(&self) -> usize {
+        self.stamps.len()
+    }
+
+    /// Whether the index holds no files.
+    pub fn is_empty(&self) -> bool {
+        self.stamps.is_empty()
+    }
+
+    /// Whether `path` is one the walk options would include: with
+    /// `skip_hidden`, nothing below a root whose name starts with a `.`.
+    pub fn is_watched(&self, path: &Path) -> bool {
+        if !self.options.skip_hidden {
+            return true;
+        }
+        let relative = self
+            .roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .unwrap_or(path);
+        !relative
+            .components()
+            .any(|c| c.as_os_str().as_encoded_bytes().starts_with(b"."))
+    }
+
+    fn insert(&mut self, path: PathBuf, stamp: FileStamp) {
+        self.by_size
+            .entry(stamp.size)
+            .or_default()
+            .push(path.clone());
+        self.stamps.insert(path, stamp);
+    }
+
+    /// Forget `path`, for example after it was deleted or renamed. When
+    /// `path` is a directory, everything that was under it is forgotten.
+    pub fn remove(&mut self, path: &Path) {
+        if self.stamps.contains_key(path) {
+            self.forget(path);
+            return;
+        }
+        let under: Vec<PathBuf> = self
+            .stamps
+            .keys()
+            .filter(|p| p.starts_with(path))
+            .cloned()
+            .collect();
+        for file in under {
+            self.forget(&file);
+        }
+    }
+
+    /// Forget the single file `path`.
+    fn forget(&mut self, path: &Path) {
+        self.hashes.remove(path);
+        if let Some(stamp) = self.stamps.remove(path)
+            && let Some(paths) = self.by_size.get_mut(&stamp.size)
+        {
+            paths.retain(|p| p != path);
+            if paths.is_empty() {
+                self.by_size.remove(&stamp.size);
+            }
+        }
+    }
+
+    /// Hash of the indexed file `path`, if it still has the size and
+    /// modification time it was indexed with.
+    ///
+    /// A file that changed is re-indexed (and hashed again if it still has
+    /// `size` bytes); one that is gone or unreadable is forgotten.
+    fn current_hash(&mut self, path: &Path, size: u64) -> Option<String> {
+        let stamp = match FileStamp::of(path) {
+            Ok(stamp) => stamp,
+            Err(_) => {
+                self.forget(path);
+                return None;
+            }
+        };
+        if self.stamps.get(path) != Some(&stamp) {
+            self.forget(path);
+            self.insert(path.to_path_buf(), stamp);
+        }
+        if stamp.size != size {
+            return None;
+        }
+        if let Some(hash) = self.hashes.get(path) {
+            return Some(hash.clone());
+        }
+        match hash_file(path) {
+            Ok(hash) => {
+                self.hashes.insert(path.to_path_buf(), hash.clone());
+                Some(hash)
+            }
+            Err(_) => {
+                self.forget(path);
+                None
+            }
+        }
+    }
+
+    /// Add a new or rewritten file, returning an existing file with the same
+    /// content if there is one.
+    ///
+    /// Anything that is not a regular file, or is hidden by the walk options,
+    /// is ignored. Empty files are indexed but never reported. Existing files
+    /// that can no longer be read are dropped from the index.
+    pub fn add(&mut self, path: &Path) -> io::Result<Option<WatchMatch>> {
+        self.forget(path);
+        let meta = fs::symlink_metadata(path)?;
+        if !meta.is_file() || !self.is_watched(path) {
+            return Ok(None);
+        }
+        let stamp = FileStamp::from_metadata(&meta);
+        let size = stamp.size;
+        let candidates = self.by_size.get(&size).cloned().unwrap_or_default();
+        if size == 0 || candidates.is_empty() {
+            self.insert(path.to_path_buf(), stamp);
+            return Ok(None);
+        }
+
+        let hash = hash_file(path)?;
+        let mut found = None;
+        for candidate in candidates {
+            if self.current_hash(&candidate, size).as_ref() == Some(&hash) {
+                found = Some(candidate);
+                break;
+            }
+        }
+
+        self.insert(path.to_path_buf(), stamp);
+        self.hashes.insert(path.to_path_buf(), hash.clone());
+        Ok(found.map(|original| WatchMatch {
+            path: path.to_path_buf(),
+            original,
+            hash,
+            size,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn new_files_are_matched_against_existing_content() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().to_path_buf();
+        fs::write(root.join("logo.png"), b"logo").unwrap();
+        fs::write(root.join("other.png"), b"abcd").unwrap();
+
+        let options = WalkOptions {
+            skip_hidden: true,
+            ..WalkOptions::default()
+        };
+        let mut index = WatchIndex::build(std::slice::from_ref(&root), &options);
+        assert_eq!(index.len(), 2);
+
+        // Same size, different content.
+        fs::write(root.join("new.png"), b"wxyz").unwrap();
+        assert_eq!(index.add(&root.join("new.png")).unwrap(), None);
+
+        fs::write(root.join("copy.png"), b"logo").unwrap();
+        let found = index.add(&root.join("copy.png")).unwrap().unwrap();
+        assert_eq!(found.original, root.join("logo.png"));
+        assert_eq!(found.size, 4);
+
+        // Hidden temporary files are ignored, and forgotten files never match.
+        fs::write(root.join(".upload.tmp"), b"logo").unwrap();
+        assert_eq!(index.add(&root.join(".upload.tmp")).unwrap(), None);
+        index.remove(&root.join("logo.png"));
+        index.remove(&root.join("copy.png"));
+        fs::write(root.join("again.png"), b"logo").unwrap();
+        assert_eq!(index.add(&root.join("again.png")).unwrap(), None);
+        assert_eq!(index.len(), 3);
+    }
+
+    #[test]
+    fn renamed_directories_are_forgotten_with_everything_under_them() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().to_path_buf();
+        fs::create_dir(root.join("batch")).unwrap();
+        fs::write(root.join("batch/a.png"), b"logo").unwrap();
+        fs::write(root.join("b.png"), b"abcd").unwrap();
+
+        let mut index = WatchIndex::build(std::slice::from_ref(&root), &WalkOptions::default());
+        // Hash `batch/a.png` so a stale hash would be cached.
+        fs::write(root.join("c.png"), b"abcd").unwrap();
+        assert!(index.add(&root.join("c.png")).unwrap().is_some());
+
+        // `mv batch batch2`: the old directory goes, its files come back.
+        fs::rename(root.join("batch"), root.join("batch2")).unwrap();
+        index.remove(&root.join("batch"));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.add(&root.join("batch2/a.png")).unwrap(), None);
+    }
+
+    #[test]
+    fn changed_originals_are_hashed_again() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().to_path_buf();
+        let original = root.join("logo.png");
+        fs::write(&original, b"logo").unwrap();
+
+        let mut index = WatchIndex::build(std::slice::from_ref(&root), &WalkOptions::default());
+        fs::write(root.join("copy.png"), b"logo").unwrap();
+        assert!(index.add(&root.join("copy.png")).unwrap().is_some());
+
+        // Rewritten behind the index's back, without an event.
+        fs::write(&original, b"LOGO").unwrap();
+        let file = fs::File::options().write(true).open(&original).unwrap();
+        file.set_modified(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap();
+        fs::remove_file(root.join("copy.png")).unwrap();
+        index.remove(&root.join("copy.png"));
+        fs::write(root.join("again.png"), b"logo").unwrap();
+        assert_eq!(index.add(&root.join("again.png")).unwrap(), None);
+    }
+}
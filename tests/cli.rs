@@ -233,4 +233,1293 @@ fn json_output_writes_report_without_deleting() {
             .any(|g| { g["files"].as_array().map(|a| a.len() >= 2).unwrap_or(false) }),
         "Expected at least one duplicate group with 2+ files"
     );
+
+    assert_eq!(parsed["schema_version"], Value::from(2));
+    assert_eq!(
+        parsed["tool_version"],
+        Value::from(env!("CARGO_PKG_VERSION"))
+    );
+    assert!(parsed["started_at"].is_string() && parsed["finished_at"].is_string());
+    let group = &parsed["duplicate_groups"][0];
+    assert_eq!(group["size"], Value::from(4));
+    assert_eq!(group["hash"].as_str().map(str::len), Some(64));
+    assert_eq!(group["files"][0]["role"], Value::from("keep"));
+    assert_eq!(group["files"][0]["path"], group["keep"]);
+    assert_eq!(group["files"][1]["role"], Value::from("dupe"));
+    assert_eq!(group["files"][1]["size"], Value::from(4));
+    assert!(group["files"][1]["mtime"].is_string());
+}
+
+#[test]
+fn resume_writes_checkpoint_and_reuses_it() {
+    let dir = TempDir::new().unwrap();
+    let scan_dir = dir.path().join("scan");
+    fs::create_dir(&scan_dir).unwrap();
+    let checkpoint = dir.path().join("checkpoint.json");
+    fs::write(scan_dir.join("a.txt"), b"dupe").unwrap();
+    fs::write(scan_dir.join("b.txt"), b"dupe").unwrap();
+
+    for _ in 0..2 {
+        binary_cmd()
+            .env("NO_COLOR", "1")
+            .arg("--dry-run")
+            .arg("--resume")
+            .arg(&checkpoint)
+            .arg(&scan_dir)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "1 duplicate file(s) can be removed",
+            ));
+
+        let contents = fs::read_to_string(&checkpoint).expect("checkpoint should exist");
+        let parsed: Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(parsed["files_discovered"], Value::from(2));
+        assert_eq!(parsed["files"].as_array().unwrap().len(), 2);
+    }
+}
+
+#[test]
+fn csv_output_writes_one_row_per_file_without_deleting() {
+    let dir = TempDir::new().unwrap();
+    let scan_dir = dir.path().join("scan");
+    fs::create_dir(&scan_dir).unwrap();
+    let keep = scan_dir.join("a.txt");
+    let dupe = scan_dir.join("b.txt");
+    fs::write(&keep, b"dupe").unwrap();
+    fs::write(&dupe, b"dupe").unwrap();
+    let csv_path = dir.path().join("out/report.csv");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--csv-output")
+        .arg(&csv_path)
+        .arg(&scan_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("CSV report written to:"));
+
+    assert!(keep.exists(), "Keep file should remain");
+    assert!(dupe.exists(), "Dupe should remain");
+
+    let contents = fs::read_to_string(&csv_path).expect("CSV report should be readable");
+    let lines: Vec<_> = contents.lines().collect();
+    assert_eq!(lines[0], "group_id,role,path,size,mtime,hash");
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with(&format!("1,keep,{},4,", keep.display())));
+    assert!(lines[2].starts_with(&format!("1,dupe,{},4,", dupe.display())));
+}
+
+#[test]
+fn ndjson_format_streams_groups_and_summary_to_stdout() {
+    let dir = TempDir::new().unwrap();
+    let keep = write_file(&dir, "a.txt", b"dupe");
+    let dupe = write_file(&dir, "b.txt", b"dupe");
+    let _ = write_file(&dir, "unique.txt", b"unique");
+
+    let output = binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--format")
+        .arg("ndjson")
+        .arg(dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let records: Vec<Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every stdout line should be JSON"))
+        .collect();
+
+    assert_eq!(records.len(), 2, "stdout was: {}", stdout);
+    assert_eq!(records[0]["type"], Value::from("group"));
+    assert_eq!(records[0]["keep"], Value::from(keep.display().to_string()));
+    assert_eq!(records[1]["type"], Value::from("summary"));
+    assert_eq!(records[1]["group_count"], Value::from(1));
+    assert_eq!(records[1]["removable_count"], Value::from(1));
+
+    assert!(keep.exists() && dupe.exists(), "NDJSON mode never deletes");
+}
+
+#[test]
+fn ndjson_format_rejects_options_it_would_ignore() {
+    let dir = TempDir::new().unwrap();
+    let _ = write_file(&dir, "a.txt", b"dupe");
+    let report = dir.path().join("report.json");
+
+    binary_cmd()
+        .arg("--format")
+        .arg("ndjson")
+        .arg("--json-output")
+        .arg(&report)
+        .arg(dir.path())
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+    assert!(!report.exists());
+
+    for ignored in [
+        &["--top", "1"][..],
+        &["--sort", "waste"],
+        &["--stats"],
+        &["--dirs"],
+        &["--tui"],
+        &["--interactive"],
+        &["--prune-empty-dirs"],
+    ] {
+        binary_cmd()
+            .arg("--format")
+            .arg("ndjson")
+            .args(ignored)
+            .arg(dir.path())
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+
+    for format in [None, Some("text")] {
+        let mut cmd = binary_cmd();
+        if let Some(format) = format {
+            cmd.arg("--format").arg(format);
+        }
+        cmd.arg("--output")
+            .arg(dir.path().join("groups.ndjson"))
+            .arg("--dry-run")
+            .arg(dir.path())
+            .assert()
+            .code(2);
+    }
+    assert!(!dir.path().join("groups.ndjson").exists());
+}
+
+#[test]
+fn html_output_writes_self_contained_page_without_deleting() {
+    let dir = TempDir::new().unwrap();
+    let scan_dir = dir.path().join("scan");
+    fs::create_dir(&scan_dir).unwrap();
+    let keep = scan_dir.join("a.txt");
+    let dupe = scan_dir.join("b.txt");
+    fs::write(&keep, b"dupe").unwrap();
+    fs::write(&dupe, b"dupe").unwrap();
+    let html_path = dir.path().join("report.html");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--html-output")
+        .arg(&html_path)
+        .arg(&scan_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("HTML report written to:"));
+
+    assert!(keep.exists() && dupe.exists(), "HTML mode never deletes");
+
+    let html = fs::read_to_string(&html_path).expect("HTML report should be readable");
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<details>"));
+    assert!(html.contains(&dupe.display().to_string()));
+}
+
+#[test]
+fn porcelain_prints_only_tab_separated_records() {
+    let dir = TempDir::new().unwrap();
+    let keep = write_file(&dir, "a.txt", b"dupe");
+    let dupe = write_file(&dir, "b.txt", b"dupe");
+
+    let output = binary_cmd()
+        .arg("--porcelain")
+        .arg(dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        format!(
+            "1\tkeep\t4\t{}\n1\tdupe\t4\t{}\n",
+            keep.display(),
+            dupe.display()
+        )
+    );
+    assert!(keep.exists() && dupe.exists(), "porcelain never deletes");
+}
+
+#[test]
+fn null_flag_terminates_records_with_nul() {
+    let dir = TempDir::new().unwrap();
+    let keep = write_file(&dir, "a.txt", b"dupe");
+    let dupe = write_file(&dir, "b.txt", b"dupe");
+
+    let output = binary_cmd().arg("-0").arg(dir.path()).output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        format!(
+            "1\tkeep\t4\t{}\01\tdupe\t4\t{}\0",
+            keep.display(),
+            dupe.display()
+        )
+    );
+}
+
+#[test]
+fn piped_output_has_no_colour_codes() {
+    let dir = TempDir::new().unwrap();
+    let _ = write_file(&dir, "a.txt", b"dupe");
+    let _ = write_file(&dir, "b.txt", b"dupe");
+
+    let output = binary_cmd()
+        .env_remove("NO_COLOR")
+        .arg("--dry-run")
+        .arg(dir.path())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[KEEP]"));
+    assert!(!stdout.contains('\u{1b}'), "stdout was: {:?}", stdout);
+}
+
+#[test]
+fn fdupes_output_style_prints_blank_line_separated_groups() {
+    let dir = TempDir::new().unwrap();
+    let first = write_file(&dir, "a.txt", b"dupe");
+    let second = write_file(&dir, "b.txt", b"dupe");
+    let _ = write_file(&dir, "unique.txt", b"unique");
+
+    let output = binary_cmd()
+        .arg("-r")
+        .arg("--output-style")
+        .arg("fdupes")
+        .arg(dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}\n{}\n\n", first.display(), second.display())
+    );
+    assert!(
+        first.exists() && second.exists(),
+        "fdupes style never deletes"
+    );
+}
+
+#[test]
+fn summarize_prints_fdupes_style_summary() {
+    let dir = TempDir::new().unwrap();
+    let _ = write_file(&dir, "a.txt", b"dupe");
+    let _ = write_file(&dir, "b.txt", b"dupe");
+    let _ = write_file(&dir, "c.txt", b"dupe");
+
+    binary_cmd()
+        .arg("-m")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout("2 duplicate files (in 1 sets), occupying 8 bytes.\n\n");
+}
+
+#[test]
+fn sort_and_top_show_biggest_offender_only() {
+    let dir = TempDir::new().unwrap();
+    let small = write_file(&dir, "a-small.txt", b"s");
+    let _ = write_file(&dir, "b-small.txt", b"s");
+    let big = write_file(&dir, "c-big.txt", b"big content");
+    let _ = write_file(&dir, "d-big.txt", b"big content");
+
+    let output = binary_cmd()
+        .arg("--porcelain")
+        .arg("--sort")
+        .arg("waste")
+        .arg("--top")
+        .arg("1")
+        .arg(dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!("1\tkeep\t11\t{}\n", big.display())));
+    assert!(!stdout.contains(&small.display().to_string()));
+    assert_eq!(stdout.lines().count(), 2);
+}
+
+#[test]
+fn stats_prints_waste_breakdown() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("team-a")).unwrap();
+    fs::create_dir(dir.path().join("team-b")).unwrap();
+    let _ = write_file(&dir, "team-a/report.pdf", b"dupe");
+    let _ = write_file(&dir, "team-b/report.pdf", b"dupe");
+
+    let output = binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dry-run")
+        .arg("--stats")
+        .arg(dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Waste statistics:"),
+        "stdout was: {}",
+        stdout
+    );
+    assert!(stdout.contains("team-b"), "stdout was: {}", stdout);
+    assert!(stdout.contains("pdf"), "stdout was: {}", stdout);
+    assert!(stdout.contains("< 1 KB"), "stdout was: {}", stdout);
+    assert!(stdout.contains("<->"), "stdout was: {}", stdout);
+
+    // The tables describe the whole scan, not only the `--top` groups.
+    let _ = write_file(&dir, "team-a/notes.txt", b"bigger dupe");
+    let _ = write_file(&dir, "team-b/notes.txt", b"bigger dupe");
+    let output = binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dry-run")
+        .arg("--stats")
+        .arg("--sort")
+        .arg("waste")
+        .arg("--top")
+        .arg("1")
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("report.pdf"), "stdout was: {}", stdout);
+    assert!(stdout.contains("pdf"), "stdout was: {}", stdout);
+    assert!(stdout.contains("txt"), "stdout was: {}", stdout);
+}
+
+#[test]
+fn dirs_reports_and_removes_redundant_subtrees() {
+    let dir = TempDir::new().unwrap();
+    for copy in ["album", "album-backup"] {
+        fs::create_dir_all(dir.path().join(copy).join("disc1")).unwrap();
+        let _ = write_file(&dir, &format!("{copy}/cover.jpg"), b"cover");
+        let _ = write_file(&dir, &format!("{copy}/disc1/track01.flac"), b"track");
+    }
+
+    let output = binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dirs")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Duplicate Directory Group 1 (2 file(s)"),
+        "stdout was: {}",
+        stdout
+    );
+    assert!(stdout.contains("[DUPE DIR]"), "stdout was: {}", stdout);
+    // Files inside the redundant subtree are not listed one by one.
+    assert!(!stdout.contains("[DUPE] /"), "stdout was: {}", stdout);
+    assert!(
+        stdout.contains("Deleted 2 file(s)"),
+        "stdout was: {}",
+        stdout
+    );
+    assert!(dir.path().join("album/disc1/track01.flac").exists());
+    assert!(!dir.path().join("album-backup").exists());
+}
+
+#[test]
+fn dirs_never_removes_files_the_scan_did_not_compare() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("a")).unwrap();
+    fs::create_dir_all(dir.path().join("b/deep/deeper")).unwrap();
+    let _ = write_file(&dir, "a/photo.jpg", b"photo");
+    let copy = write_file(&dir, "b/photo.jpg", b"photo");
+    let secret = write_file(&dir, "b/.secret", b"secret");
+    let precious = write_file(&dir, "b/deep/deeper/precious", b"precious");
+
+    let output = binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--dirs")
+        .arg("--skip-hidden")
+        .arg("--max-depth")
+        .arg("2")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[DUPE DIR]"), "stdout was: {}", stdout);
+    assert!(stdout.contains("[KEPT DIR]"), "stdout was: {}", stdout);
+    assert!(
+        stdout.contains("Deleted 1 file(s)"),
+        "stdout was: {}",
+        stdout
+    );
+    assert!(!copy.exists());
+    assert!(secret.exists());
+    assert!(precious.exists());
+}
+
+#[test]
+fn empty_files_are_ignored_by_default_and_reported_on_request() {
+    let dir = TempDir::new().unwrap();
+    let _ = write_file(&dir, "a.lock", b"");
+    let _ = write_file(&dir, "b.lock", b"");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dry-run")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No duplicates found"))
+        .stdout(predicate::str::contains("[EMPTY]").not());
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dry-run")
+        .arg("--empty-files")
+        .arg("report")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[EMPTY]").count(2))
+        .stdout(predicate::str::contains("No duplicates found"));
+}
+
+#[test]
+fn prune_empty_dirs_removes_directories_emptied_by_deletion() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("a")).unwrap();
+    fs::create_dir_all(dir.path().join("z/nested")).unwrap();
+    fs::create_dir_all(dir.path().join("untouched")).unwrap();
+    let keep = write_file(&dir, "a/file.txt", b"dupe");
+    let _ = write_file(&dir, "z/nested/file.txt", b"dupe");
+
+    let output = binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--prune-empty-dirs")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Removed 2 empty director(ies)."),
+        "stdout was: {}",
+        stdout
+    );
+    assert!(keep.exists());
+    assert!(!dir.path().join("z").exists());
+    assert!(dir.path().join("untouched").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_never_reported_as_duplicates_of_their_target() {
+    let dir = TempDir::new().unwrap();
+    let target = write_file(&dir, "target.txt", b"content");
+    std::os::unix::fs::symlink(&target, dir.path().join("link.txt")).unwrap();
+
+    for follow in [false, true] {
+        let mut cmd = binary_cmd();
+        cmd.env("NO_COLOR", "1").arg("--dry-run");
+        if follow {
+            cmd.arg("--follow-symlinks");
+        }
+        cmd.arg(dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("No duplicates found"));
+    }
+    assert!(target.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn type_any_lists_symlinked_copies_without_deleting_them() {
+    let dir = TempDir::new().unwrap();
+    let outside = TempDir::new().unwrap();
+    let original = write_file(&dir, "a.txt", b"content");
+    let target = write_file(&outside, "b.txt", b"content");
+    let link = dir.path().join("link.txt");
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dry-run")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[LINK]").not());
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--type")
+        .arg("any")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "[LINK] {} same content as {}",
+            link.display(),
+            original.display()
+        )))
+        .stdout(predicate::str::contains("No duplicates found"));
+    assert!(link.exists() && original.exists() && target.exists());
+}
+
+#[test]
+fn one_file_system_scan_shows_group_mounts() {
+    let dir = TempDir::new().unwrap();
+    let _ = write_file(&dir, "a.txt", b"dupe");
+    let _ = write_file(&dir, "b.txt", b"dupe");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dry-run")
+        .arg("-x")
+        .arg("--show-mounts")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[MOUNT] /"))
+        .stdout(predicate::str::contains("cannot be hard-linked").not());
+}
+
+#[test]
+fn max_depth_and_skip_hidden_limit_the_walk() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("deep/er")).unwrap();
+    let _ = write_file(&dir, "a.txt", b"dupe");
+    let _ = write_file(&dir, ".a.txt", b"dupe");
+    let _ = write_file(&dir, "deep/er/a.txt", b"dupe");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dry-run")
+        .arg("--max-depth")
+        .arg("1")
+        .arg("--skip-hidden")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No duplicates found"));
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--dry-run")
+        .arg("--max-depth")
+        .arg("1")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 duplicate file(s)"));
+}
+
+#[test]
+fn config_file_and_profile_supply_defaults() {
+    let dir = TempDir::new().unwrap();
+    let work = TempDir::new().unwrap();
+    let _ = write_file(&dir, "a.txt", b"dupe");
+    let _ = write_file(&dir, "b.txt", b"dupe");
+    fs::write(
+        work.path().join("ddupe.toml"),
+        "dry-run = true\n[profile.porcelain]\nporcelain = true\n",
+    )
+    .unwrap();
+
+    // Top-level settings apply: nothing is deleted, no prompt is needed.
+    binary_cmd()
+        .current_dir(work.path())
+        .env("XDG_CONFIG_HOME", work.path())
+        .env("NO_COLOR", "1")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Dry run:"));
+
+    // A profile layers its own settings on top.
+    binary_cmd()
+        .current_dir(work.path())
+        .env("XDG_CONFIG_HOME", work.path())
+        .arg("--profile")
+        .arg("porcelain")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("1\tkeep\t4\t"));
+
+    fs::write(
+        work.path().join("ddupe.toml"),
+        "keep-strategy = \"newest\"\n",
+    )
+    .unwrap();
+    binary_cmd()
+        .current_dir(work.path())
+        .env("XDG_CONFIG_HOME", work.path())
+        .arg(dir.path())
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("unknown option 'keep-strategy'"));
+}
+
+#[test]
+fn command_line_options_override_conflicting_config_settings() {
+    let dir = TempDir::new().unwrap();
+    let work = TempDir::new().unwrap();
+    let _ = write_file(&dir, "a.txt", b"dupe");
+    let dupe = write_file(&dir, "b.txt", b"dupe");
+    let report = work.path().join("report.json");
+    fs::write(
+        work.path().join("ddupe.toml"),
+        "dry-run = true\nporcelain = true\n",
+    )
+    .unwrap();
+
+    // `porcelain` cannot be combined with --json-output, so it is dropped.
+    binary_cmd()
+        .current_dir(work.path())
+        .env("XDG_CONFIG_HOME", work.path())
+        .env("NO_COLOR", "1")
+        .arg("--json-output")
+        .arg(&report)
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("JSON report written to"));
+    assert!(report.exists());
+
+    // --no-dry-run turns the configured dry run off.
+    fs::write(work.path().join("ddupe.toml"), "dry-run = true\n").unwrap();
+    binary_cmd()
+        .current_dir(work.path())
+        .env("XDG_CONFIG_HOME", work.path())
+        .env("NO_COLOR", "1")
+        .arg("--no-dry-run")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Deleted 1 file(s)"));
+    assert!(!dupe.exists());
+}
+
+#[test]
+fn protected_paths_are_never_deleted() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("legal-hold")).unwrap();
+    let keep = write_file(&dir, "a.txt", b"dupe");
+    let held = write_file(&dir, "legal-hold/b.txt", b"dupe");
+    let other = write_file(&dir, "c.txt", b"dupe");
+
+    let output = binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--protect")
+        .arg("**/legal-hold")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("legal-hold"), "stdout was: {}", stdout);
+    assert!(keep.exists());
+    assert!(held.exists());
+    assert!(!other.exists());
+
+    // Patterns on the command line add to the configured ones.
+    let work = TempDir::new().unwrap();
+    fs::write(
+        work.path().join("ddupe.toml"),
+        "protect = [\"**/legal-hold\"]\n",
+    )
+    .unwrap();
+    let other = write_file(&dir, "c.txt", b"dupe");
+    let archived = write_file(&dir, "d.archived", b"dupe");
+    binary_cmd()
+        .current_dir(work.path())
+        .env("XDG_CONFIG_HOME", work.path())
+        .env("NO_COLOR", "1")
+        .arg("--protect")
+        .arg("**/*.archived")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .assert()
+        .success();
+    assert!(keep.exists());
+    assert!(held.exists());
+    assert!(archived.exists());
+    assert!(!other.exists());
+}
+
+#[test]
+fn max_delete_count_refuses_oversized_plans() {
+    let dir = TempDir::new().unwrap();
+    let _ = write_file(&dir, "a.txt", b"dupe");
+    let b = write_file(&dir, "b.txt", b"dupe");
+    let c = write_file(&dir, "c.txt", b"dupe");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--max-delete-count")
+        .arg("1")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "the plan deletes 2 file(s), more than the limit of 1",
+        ));
+
+    assert!(b.exists());
+    assert!(c.exists());
+
+    // Files inside a redundant directory count one by one.
+    let tree = TempDir::new().unwrap();
+    for copy in ["a", "b"] {
+        fs::create_dir(tree.path().join(copy)).unwrap();
+        let _ = write_file(&tree, &format!("{copy}/one.txt"), b"one");
+        let _ = write_file(&tree, &format!("{copy}/two.txt"), b"two");
+    }
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--dirs")
+        .arg("--max-delete-count")
+        .arg("1")
+        .arg(tree.path())
+        .write_stdin("y\n")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "the plan deletes 2 file(s), more than the limit of 1",
+        ));
+    assert!(tree.path().join("b/two.txt").exists());
+}
+
+#[test]
+fn audit_log_records_every_deletion() {
+    let dir = TempDir::new().unwrap();
+    let keep = write_file(&dir, "a.txt", b"dupe");
+    let dupe = write_file(&dir, "b.txt", b"dupe");
+    let log = dir.path().join("logs/audit.jsonl");
+
+    for _ in 0..2 {
+        let _ = write_file(&dir, "b.txt", b"dupe");
+        binary_cmd()
+            .env("NO_COLOR", "1")
+            .arg("--audit-log")
+            .arg(&log)
+            .arg(dir.path())
+            .write_stdin("y\n")
+            .assert()
+            .success();
+    }
+
+    let text = fs::read_to_string(&log).unwrap();
+    let entries: Vec<Value> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2, "log was: {}", text);
+    for entry in &entries {
+        assert_eq!(entry["action"], "DELETED");
+        assert_eq!(entry["kind"], "file");
+        assert_eq!(entry["path"], dupe.to_str().unwrap());
+        assert_eq!(entry["keep"], keep.to_str().unwrap());
+        assert_eq!(entry["size"], 4);
+        assert_eq!(entry["hash"].as_str().unwrap().len(), 64);
+    }
+    assert!(keep.exists());
+    assert!(!dupe.exists());
+
+    // A redundant directory is logged with the bytes its files freed.
+    let tree = TempDir::new().unwrap();
+    fs::create_dir_all(tree.path().join("original")).unwrap();
+    fs::create_dir_all(tree.path().join("copy")).unwrap();
+    for sub in ["original", "copy"] {
+        let _ = write_file(&tree, &format!("{sub}/a.txt"), b"dupe");
+        let _ = write_file(&tree, &format!("{sub}/b.txt"), b"other");
+    }
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--dirs")
+        .arg("--audit-log")
+        .arg(&log)
+        .arg(tree.path())
+        .write_stdin("y\ny\ny\n")
+        .assert()
+        .success();
+    assert!(tree.path().join("copy").exists() != tree.path().join("original").exists());
+
+    let text = fs::read_to_string(&log).unwrap();
+    let last: Value = serde_json::from_str(text.lines().last().unwrap()).unwrap();
+    assert_eq!(last["kind"], "directory");
+    assert_eq!(last["size"], 9, "log was: {}", text);
+}
+
+#[test]
+fn tui_requires_a_terminal_and_conflicts_with_dry_run() {
+    let dir = TempDir::new().unwrap();
+    let a = write_file(&dir, "a.txt", b"dupe");
+    let b = write_file(&dir, "b.txt", b"dupe");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--tui")
+        .arg(dir.path())
+        .write_stdin("y\n")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--tui needs an interactive terminal",
+        ));
+    assert!(a.exists());
+    assert!(b.exists());
+
+    binary_cmd()
+        .arg("--tui")
+        .arg("--dry-run")
+        .arg(dir.path())
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn interactive_rule_keeps_copies_under_a_directory() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("archive")).unwrap();
+    fs::create_dir(dir.path().join("inbox")).unwrap();
+    let kept_a = write_file(&dir, "archive/a.txt", b"first");
+    let kept_b = write_file(&dir, "archive/b.txt", b"second");
+    let dupe_a = write_file(&dir, "inbox/a.txt", b"first");
+    let dupe_b = write_file(&dir, "inbox/b.txt", b"second");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("-i")
+        .arg(dir.path())
+        .write_stdin(format!("r {}\n", dir.path().join("archive").display()))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("modified"))
+        .stdout(predicate::str::contains("[RULE] Keeping"));
+
+    assert!(kept_a.exists() && kept_b.exists());
+    assert!(!dupe_a.exists() && !dupe_b.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn watch_reports_and_deletes_new_duplicates() {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command as StdCommand, Stdio};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let dir = TempDir::new().unwrap();
+    let original = write_file(&dir, "logo.png", b"logo");
+    let log = dir.path().join("audit.jsonl");
+
+    let mut child = StdCommand::new(env!("CARGO_BIN_EXE_ddupe"))
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("watch")
+        .arg("--delete")
+        .arg("--audit-log")
+        .arg(&log)
+        .arg(dir.path())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (tx, rx) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let wait_for = |needle: &str| loop {
+        let line = rx
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap_or_else(|_| panic!("no line containing '{needle}'"));
+        if line.contains(needle) {
+            return line;
+        }
+    };
+
+    wait_for("Watching 1 existing file(s)");
+    let unique = write_file(&dir, "unique.png", b"new!");
+    let copy = write_file(&dir, "copy.png", b"logo");
+    let line = wait_for("[DUPE]");
+    wait_for("[DELETED]");
+    // The audit entry is written just after the deletion is printed.
+    for _ in 0..100 {
+        if fs::read_to_string(&log).is_ok_and(|text| text.contains("\"DELETED\"")) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(
+        line.contains("copy.png") && line.contains("logo.png"),
+        "{line}"
+    );
+    assert!(original.exists());
+    assert!(unique.exists());
+    assert!(!copy.exists());
+    assert!(fs::read_to_string(&log).unwrap().contains("\"DELETED\""));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn watch_forgets_renamed_directories_and_enforces_limits() {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command as StdCommand, Stdio};
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("batch")).unwrap();
+    let _ = write_file(&dir, "batch/a.png", b"logo");
+    let _ = write_file(&dir, "b.png", b"abcd");
+
+    let mut child = StdCommand::new(env!("CARGO_BIN_EXE_ddupe"))
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("watch")
+        .arg("--delete")
+        .arg("--max-delete-count")
+        .arg("1")
+        .arg(dir.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (tx, rx) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    let wait_for = |needle: &str| loop {
+        let line = rx
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap_or_else(|_| panic!("no line containing '{needle}'"));
+        if line.contains(needle) {
+            return line;
+        }
+    };
+
+    wait_for("Watching 2 existing file(s)");
+    // A same-sized file hashes `batch/a.png` into the index first.
+    let _ = write_file(&dir, "c.png", b"wxyz");
+    std::thread::sleep(Duration::from_millis(300));
+    fs::rename(dir.path().join("batch"), dir.path().join("batch2")).unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    let copy = write_file(&dir, "copy.png", b"logo");
+    let line = wait_for("[DUPE]");
+    assert!(line.contains("copy.png"), "{line}");
+    wait_for("[DELETED]");
+    assert!(!copy.exists());
+    assert!(dir.path().join("batch2/a.png").exists());
+
+    // The second deletion would go over --max-delete-count.
+    let again = write_file(&dir, "again.png", b"logo");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        assert!(Instant::now() < deadline, "watch did not stop");
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let mut stderr = String::new();
+    std::io::Read::read_to_string(&mut child.stderr.take().unwrap(), &mut stderr).unwrap();
+    assert_eq!(status.code(), Some(1));
+    assert!(stderr.contains("more than the limit of 1"), "{stderr}");
+    assert!(again.exists());
+}
+
+#[test]
+fn query_answers_from_the_index_of_an_earlier_scan() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("archive")).unwrap();
+    let original = write_file(&dir, "archive/a.jpg", b"photo");
+    let _ = write_file(&dir, "archive/b.jpg", b"other");
+    let index = dir.path().join("index.json");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--index")
+        .arg(&index)
+        .arg("--dry-run")
+        .arg(dir.path().join("archive"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Index updated: 2 file(s)"));
+
+    let incoming = write_file(&dir, "incoming.jpg", b"photo");
+    let output = binary_cmd()
+        .arg("--no-config")
+        .arg("query")
+        .arg("--index")
+        .arg(&index)
+        .arg("--json")
+        .arg(&incoming)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let result: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["copies"][0]["status"], "current");
+    assert!(
+        result["copies"][0]["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("a.jpg")
+    );
+    assert!(original.exists());
+
+    let unknown = write_file(&dir, "unknown.jpg", b"never seen");
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--index")
+        .arg(&index)
+        .arg("query")
+        .arg(&unknown)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("[NEW]"));
+
+    binary_cmd()
+        .arg("--no-config")
+        .arg("query")
+        .arg(&unknown)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("no index to query"));
+}
+
+#[test]
+fn filtered_rescans_keep_index_entries_they_did_not_walk() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir_all(dir.path().join("archive/2019")).unwrap();
+    let _ = write_file(&dir, "archive/top.jpg", b"top");
+    let deep = write_file(&dir, "archive/2019/deep.jpg", b"deep");
+    let index = dir.path().join("index.json");
+
+    for depth in [None, Some("1")] {
+        let mut cmd = binary_cmd();
+        cmd.arg("--no-config").arg("--index").arg(&index);
+        if let Some(depth) = depth {
+            cmd.arg("--max-depth").arg(depth);
+        }
+        cmd.arg("--dry-run")
+            .arg(dir.path().join("archive"))
+            .assert()
+            .success();
+    }
+
+    let incoming = write_file(&dir, "incoming.jpg", b"deep");
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--index")
+        .arg(&index)
+        .arg("query")
+        .arg(&incoming)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(deep.display().to_string()));
+}
+
+#[test]
+fn manifests_are_exported_and_stand_in_for_an_offline_archive() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("archive")).unwrap();
+    let original = write_file(&dir, "archive/a.jpg", b"photo");
+    let _ = write_file(&dir, "archive/b.jpg", b"other");
+    let sums = dir.path().join("archive.sha256");
+    let deep = dir.path().join("archive.hashdeep");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--dry-run")
+        .arg("--manifest-output")
+        .arg(&sums)
+        .arg(dir.path().join("archive"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Manifest written: 2 file(s)"));
+    let text = fs::read_to_string(&sums).unwrap();
+    let first = text.lines().next().unwrap();
+    assert_eq!(first.len(), 64 + 2 + original.to_str().unwrap().len());
+    assert!(first.ends_with(&format!("  {}", original.display())));
+
+    binary_cmd()
+        .arg("--no-config")
+        .arg("--dry-run")
+        .arg("--manifest-output")
+        .arg(&deep)
+        .arg("--manifest-format")
+        .arg("hashdeep")
+        .arg(dir.path().join("archive"))
+        .assert()
+        .success();
+    let text = fs::read_to_string(&deep).unwrap();
+    assert!(text.starts_with("%%%% HASHDEEP-1.0\n%%%% size,sha256,filename\n"));
+    assert!(text.contains(&format!(",{}\n", original.display())));
+
+    // The archive goes offline; its manifest still finds the local copy, but
+    // only deletes it when trusted.
+    fs::remove_dir_all(dir.path().join("archive")).unwrap();
+    fs::create_dir(dir.path().join("local")).unwrap();
+    let copy = write_file(&dir, "local/copy.jpg", b"photo");
+    let unique = write_file(&dir, "local/unique.jpg", b"unique");
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--manifest")
+        .arg(&deep)
+        .arg(dir.path().join("local"))
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(original.to_str().unwrap()))
+        .stdout(predicate::str::contains("[LISTED]"))
+        .stdout(predicate::str::contains("pass --trust-manifest"));
+    assert!(copy.exists());
+
+    binary_cmd()
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--manifest")
+        .arg(&deep)
+        .arg("--trust-manifest")
+        .arg(dir.path().join("local"))
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(original.to_str().unwrap()));
+    assert!(!copy.exists());
+    assert!(unique.exists());
+
+    binary_cmd()
+        .arg("--no-config")
+        .arg("--manifest")
+        .arg(dir.path().join("missing.sha256"))
+        .arg(dir.path().join("local"))
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("could not read manifest"));
+}
+
+#[test]
+fn manifest_listing_the_scanned_files_never_makes_them_dupes() {
+    let dir = TempDir::new().unwrap();
+    fs::create_dir(dir.path().join("photos")).unwrap();
+    let photo = write_file(&dir, "photos/a.jpg", b"photo");
+    let hash = ddupe::hash_file(&photo).unwrap();
+    // As written by `cd photos && sha256sum a.jpg > m.txt`.
+    let manifest = write_file(&dir, "photos/m.txt", format!("{hash}  a.jpg\n").as_bytes());
+
+    binary_cmd()
+        .current_dir(dir.path())
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--manifest")
+        .arg(&manifest)
+        .arg("--trust-manifest")
+        .arg("photos")
+        .write_stdin("y\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No duplicates found"));
+    assert!(photo.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn interrupting_a_scan_before_any_duplicate_exits_130() {
+    use std::process::{Command as StdCommand, Stdio};
+    use std::time::{Duration, Instant};
+
+    let dir = TempDir::new().unwrap();
+    let _ = write_file(&dir, "small.txt", b"unique");
+    // Large enough that the interrupt lands while the first copy is hashed;
+    // the second copy is then never read.
+    for name in ["big-1.bin", "big-2.bin"] {
+        fs::File::create(dir.path().join(name))
+            .unwrap()
+            .set_len(64 << 20)
+            .unwrap();
+    }
+
+    let child = StdCommand::new(env!("CARGO_BIN_EXE_ddupe"))
+        .env("NO_COLOR", "1")
+        .arg("--no-config")
+        .arg("--dry-run")
+        .arg(dir.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Wait until ddupe has a big file open, i.e. is hashing it.
+    let fds = format!("/proc/{}/fd", child.id());
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        assert!(Instant::now() < deadline, "ddupe never opened a big file");
+        let hashing_big = fs::read_dir(&fds).into_iter().flatten().any(|fd| {
+            fd.ok()
+                .and_then(|fd| fs::read_link(fd.path()).ok())
+                .is_some_and(|target| target.extension().is_some_and(|ext| ext == "bin"))
+        });
+        if hashing_big {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    StdCommand::new("kill")
+        .arg("-INT")
+        .arg(child.id().to_string())
+        .status()
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(130), "stdout was: {stdout}");
+    assert!(
+        !stdout.contains("No duplicates found"),
+        "stdout was: {stdout}"
+    );
+    assert!(stdout.contains("Interrupted:"), "stdout was: {stdout}");
 }